@@ -52,6 +52,21 @@ impl Aabb {
         }
     }
 
+    pub fn centroid(&self) -> Vec3 {
+        Vec3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size().max(0.0);
+        let dy = self.y.size().max(0.0);
+        let dz = self.z.size().max(0.0);
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
     pub fn hit(&self, r: &crate::ray::Ray, t: &Interval) -> bool {
         let mut tmin = t.min;
         let mut tmax = t.max;
@@ -73,6 +88,28 @@ impl Aabb {
         true
     }
 
+    /// Slab test with the reciprocal of the ray direction computed up front,
+    /// for callers testing the same ray against many boxes.
+    pub fn hit_with_inverse(&self, origin: Vec3, inv_dir: Vec3, t: &Interval) -> bool {
+        let mut tmin = t.min;
+        let mut tmax = t.max;
+
+        for a in 0..3 {
+            let mut t0 = (self.axis(a).min - origin[a]) * inv_dir[a];
+            let mut t1 = (self.axis(a).max - origin[a]) * inv_dir[a];
+            if inv_dir[a] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            tmin = if t0 > tmin { t0 } else { tmin };
+            tmax = if t1 < tmax { t1 } else { tmax };
+
+            if tmax <= tmin {
+                return false;
+            }
+        }
+        true
+    }
+
     fn pad_to_minimums(&self) -> Self {
         let delta = 0.0001;
         let x = if self.x.size() < delta {
@@ -95,16 +132,16 @@ impl Aabb {
 
     pub const EMPTY: Aabb = Aabb {
         x: Interval {
-            min: std::f64::INFINITY,
-            max: std::f64::NEG_INFINITY,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
         },
         y: Interval {
-            min: std::f64::INFINITY,
-            max: std::f64::NEG_INFINITY,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
         },
         z: Interval {
-            min: std::f64::INFINITY,
-            max: std::f64::NEG_INFINITY,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
         },
     };
 }
@@ -0,0 +1,151 @@
+//! Arbitrary output variables: what the camera rays hit first, written next to
+//! the beauty image for compositing and denoising.
+
+use crate::{
+    color::{srgb_to_linear, Color},
+    framebuffer::FrameBuffer,
+};
+
+/// A quantity of the first hit of the camera rays. Pixels average it over
+/// several rays, except for the ids, which are taken from the ray through
+/// the pixel's first sample. Pixels where nothing is hit are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance from the camera along the view direction, or along the ray
+    /// for the panoramic projections, in all channels.
+    Depth,
+    /// World space shading normal, facing the camera.
+    Normal,
+    /// Surface color of the material; the background where nothing is hit.
+    Albedo,
+    /// World space position.
+    Position,
+    /// Texture coordinates in the red and green channels.
+    Uv,
+    /// [`crate::hittable::next_object_id`] of the primitive, in all channels.
+    ObjectId,
+    /// [`crate::material::next_material_id`] of the material, in all
+    /// channels.
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::Uv,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    /// Names of the channels that hold the variable, in the order of the
+    /// color components.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Uv => &["U", "V"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+        }
+    }
+
+    /// Maps the raw values of `image` to colors that can be looked at in an
+    /// 8-bit image: depth from white up close to black far away, normals
+    /// and UVs as colors, positions scaled to the bounds of the image and
+    /// ids as random colors.
+    pub fn visualize(self, image: &FrameBuffer) -> FrameBuffer {
+        let pixels = image.pixels();
+        let colors = match self {
+            Aov::Depth => {
+                let hits = pixels.iter().map(|c| c.x()).filter(|&d| d > 0.0);
+                let near = hits.clone().fold(f64::INFINITY, f64::min);
+                let far = hits.fold(0.0, f64::max);
+                let range = (far - near).max(f64::EPSILON);
+                pixels
+                    .iter()
+                    .map(|c| {
+                        if c.x() <= 0.0 {
+                            return Color::default();
+                        }
+                        // The farthest hits stay grey, to tell them from misses.
+                        let d = 1.0 - 0.8 * (c.x() - near) / range;
+                        Color::new(d, d, d)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            Aov::Normal => pixels
+                .iter()
+                .map(|&n| {
+                    if n.length_squared() > 0.0 {
+                        0.5 * n + Color::new(0.5, 0.5, 0.5)
+                    } else {
+                        n
+                    }
+                })
+                .collect(),
+            Aov::Albedo | Aov::Uv => pixels.to_vec(),
+            Aov::Position => {
+                let mut min = Color::new(f64::MAX, f64::MAX, f64::MAX);
+                let mut max = -min;
+                for p in pixels {
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(p[axis]);
+                        max[axis] = max[axis].max(p[axis]);
+                    }
+                }
+                let size = max - min;
+                let extent = size.x().max(size.y()).max(size.z()).max(f64::EPSILON);
+                pixels.iter().map(|&p| (p - min) / extent).collect()
+            }
+            Aov::ObjectId | Aov::MaterialId => {
+                pixels.iter().map(|c| id_color(c.x() as u32)).collect()
+            }
+        };
+
+        // Saving sRGB encodes the colors, which would wash them out.
+        let colors = colors
+            .into_iter()
+            .map(|c| {
+                Color::new(
+                    srgb_to_linear(c.x()),
+                    srgb_to_linear(c.y()),
+                    srgb_to_linear(c.z()),
+                )
+            })
+            .collect();
+        FrameBuffer::from_pixels(image.width(), image.height(), colors)
+    }
+}
+
+/// A random but fixed color for `id`, and black for no id.
+fn id_color(id: u32) -> Color {
+    if id == 0 {
+        return Color::default();
+    }
+
+    let mut h = id.wrapping_mul(0x9e37_79b9);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    let channel = |shift: u32| 0.2 + 0.8 * ((h >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
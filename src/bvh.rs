@@ -5,118 +5,331 @@ use crate::{
     hittable::{HitRecord, Hittable},
     interval::Interval,
     list::HittableList,
-    ray::Ray,
+    ray::{Point3, Ray},
+    vec3::Vec3,
 };
 
-enum BvhNode {
-    Branch { left: Box<Bvh>, right: Box<Bvh> },
-    Leaf(Box<dyn Hittable>),
+/// How [`Bvh`] partitions the primitives of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMethod {
+    /// Binned surface area heuristic, with leaves of up to
+    /// [`MAX_LEAF_SIZE`] primitives.
+    #[default]
+    Sah,
+    /// Sorts along the longest axis and splits at the median, one primitive
+    /// per leaf.
+    Median,
 }
 
-pub struct Bvh {
-    tree: BvhNode,
+/// Primitives per leaf above which the SAH builder always splits.
+pub const MAX_LEAF_SIZE: usize = 4;
+
+const SAH_BINS: usize = 12;
+// Costs relative to intersecting one primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+const INTERSECTION_COST: f64 = 1.0;
+
+/// Subtrees deeper than this are built with the median split, which keeps
+/// the traversal stack within `MAX_DEPTH` entries.
+const MAX_SAH_DEPTH: usize = 32;
+const MAX_DEPTH: usize = 64;
+
+/// A node of the flattened tree. Its first child directly follows it in
+/// `Bvh::nodes`, so only the index of the second one is stored.
+struct LinearNode {
     bbox: Aabb,
+    /// First primitive of a leaf, or the second child of a branch.
+    offset: u32,
+    /// Number of primitives; zero for branches.
+    count: u16,
+    /// Axis a branch was split along.
+    axis: u8,
+}
+
+/// Bounding volume hierarchy stored as a flat array of nodes in depth-first
+/// order, with the leaves pointing into a single array of primitives.
+pub struct Bvh {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Box<dyn Hittable>>,
 }
 
 impl Bvh {
+    /// Builds the hierarchy with the default [`SplitMethod::Sah`].
     pub fn new(list: HittableList) -> Self {
-        let len = list.objects.len();
-        Self::build(list.objects, 0, len)
+        Self::with_split_method(list, SplitMethod::default())
     }
 
-    fn build(src_objects: Vec<Box<dyn Hittable>>, start: usize, end: usize) -> Self {
-        let mut bbox = Aabb::EMPTY;
-        src_objects[start..end].iter().for_each(|object| {
-            bbox = Aabb::new_from_boxes(bbox, object.bounding_box().unwrap());
+    pub fn with_split_method(list: HittableList, method: SplitMethod) -> Self {
+        let objects = list
+            .objects
+            .into_iter()
+            .map(|object| {
+                let bbox = object.bounding_box().unwrap();
+                (bbox, object)
+            })
+            .collect::<Vec<_>>();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * objects.len()),
+            primitives: Vec::with_capacity(objects.len()),
+        };
+        if !objects.is_empty() {
+            match method {
+                SplitMethod::Sah => bvh.build_sah(objects, 0),
+                SplitMethod::Median => bvh.build_median(objects),
+            };
+        }
+        bvh
+    }
+
+    fn push_leaf(&mut self, objects: Vec<(Aabb, Box<dyn Hittable>)>, bbox: Aabb) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox,
+            offset: self.primitives.len() as u32,
+            count: objects.len() as u16,
+            axis: 0,
         });
+        self.primitives
+            .extend(objects.into_iter().map(|(_, object)| object));
+        index
+    }
+
+    fn build_median(&mut self, mut objects: Vec<(Aabb, Box<dyn Hittable>)>) -> usize {
+        let bbox = bounds(&objects);
+
+        if objects.len() == 1 {
+            return self.push_leaf(objects, bbox);
+        }
 
         let axis = bbox.longest_axis();
+        objects.sort_by(|a, b| box_compare(&a.0, &b.0, axis));
 
-        let comparator = match axis {
-            0 => box_compare_x,
-            1 => box_compare_y,
-            2 => box_compare_z,
-            _ => panic!("Invalid axis"),
+        let right = objects.split_off(objects.len() / 2);
+        self.push_split(bbox, axis, objects, right, |bvh, objects| {
+            bvh.build_median(objects)
+        })
+    }
+
+    fn build_sah(&mut self, mut objects: Vec<(Aabb, Box<dyn Hittable>)>, depth: usize) -> usize {
+        if depth >= MAX_SAH_DEPTH {
+            return self.build_median(objects);
+        }
+
+        let bbox = bounds(&objects);
+        let count = objects.len();
+
+        if count == 1 {
+            return self.push_leaf(objects, bbox);
+        }
+
+        let mut centroid_bounds = Aabb::EMPTY;
+        for (object_bbox, _) in &objects {
+            let c = object_bbox.centroid();
+            centroid_bounds = Aabb::new_from_boxes(centroid_bounds, Aabb::new_from_points(c, c));
+        }
+
+        let Some(split) = find_sah_split(&objects, &centroid_bounds) else {
+            // Every centroid coincides, so no plane separates them.
+            if count <= MAX_LEAF_SIZE {
+                return self.push_leaf(objects, bbox);
+            }
+            let right = objects.split_off(count / 2);
+            return self.push_split(bbox, bbox.longest_axis(), objects, right, |bvh, objects| {
+                bvh.build_sah(objects, depth + 1)
+            });
         };
 
-        let mut object = src_objects;
+        let leaf_cost = count as f64 * INTERSECTION_COST;
+        let split_cost = TRAVERSAL_COST + split.cost / bbox.surface_area() * INTERSECTION_COST;
+        if count <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+            return self.push_leaf(objects, bbox);
+        }
 
-        let object_span = object.len();
+        let (left, right) = objects
+            .into_iter()
+            .partition::<Vec<_>, _>(|(object_bbox, _)| {
+                bin_index(object_bbox, &centroid_bounds, split.axis) < split.bin
+            });
+        self.push_split(bbox, split.axis, left, right, |bvh, objects| {
+            bvh.build_sah(objects, depth + 1)
+        })
+    }
 
-        match object_span {
-            1 => {
-                let bbox = object[start].bounding_box().unwrap();
-                let leaf = object.pop().unwrap();
-                Self {
-                    tree: BvhNode::Leaf(leaf),
-                    bbox,
-                }
+    fn push_split(
+        &mut self,
+        bbox: Aabb,
+        axis: usize,
+        left: Vec<(Aabb, Box<dyn Hittable>)>,
+        right: Vec<(Aabb, Box<dyn Hittable>)>,
+        mut build: impl FnMut(&mut Self, Vec<(Aabb, Box<dyn Hittable>)>) -> usize,
+    ) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis: axis as u8,
+        });
+        build(self, left);
+        let second = build(self, right);
+        self.nodes[index].offset = second as u32;
+        index
+    }
+}
+
+struct SahSplit {
+    axis: usize,
+    /// Primitives in bins below this one go to the left child.
+    bin: usize,
+    /// Sum of the child surface areas weighted by their primitive counts.
+    cost: f64,
+}
+
+fn find_sah_split(
+    objects: &[(Aabb, Box<dyn Hittable>)],
+    centroid_bounds: &Aabb,
+) -> Option<SahSplit> {
+    let mut best: Option<SahSplit> = None;
+
+    for axis in 0..3 {
+        if centroid_bounds.axis(axis).size() <= 0.0 {
+            continue;
+        }
+
+        let mut bins = [(Aabb::EMPTY, 0usize); SAH_BINS];
+        for (object_bbox, _) in objects {
+            let bin = &mut bins[bin_index(object_bbox, centroid_bounds, axis)];
+            bin.0 = Aabb::new_from_boxes(bin.0, *object_bbox);
+            bin.1 += 1;
+        }
+
+        // Sweep from the right to get the area and count on that side of every
+        // plane, then from the left to evaluate each of them.
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let (mut bbox, mut count) = (Aabb::EMPTY, 0);
+        for i in (1..SAH_BINS).rev() {
+            bbox = Aabb::new_from_boxes(bbox, bins[i].0);
+            count += bins[i].1;
+            right_area[i] = bbox.surface_area();
+            right_count[i] = count;
+        }
+
+        let (mut bbox, mut count) = (Aabb::EMPTY, 0);
+        for i in 1..SAH_BINS {
+            bbox = Aabb::new_from_boxes(bbox, bins[i - 1].0);
+            count += bins[i - 1].1;
+            if count == 0 || right_count[i] == 0 {
+                continue;
             }
 
-            _ => {
-                object.sort_by(comparator);
-                let mid = object_span / 2;
-                let right = Box::new(Self::build(object.drain(mid..).collect(), start, mid));
-                let left = Box::new(Self::build(object, start, mid));
-                let l = left.bounding_box().unwrap();
-                let r = right.bounding_box().unwrap();
-                Self {
-                    tree: BvhNode::Branch { left, right },
-                    bbox: Aabb::new_from_boxes(l, r),
-                }
+            let cost = count as f64 * bbox.surface_area() + right_count[i] as f64 * right_area[i];
+            if best.as_ref().is_none_or(|b| cost < b.cost) {
+                best = Some(SahSplit { axis, bin: i, cost });
             }
         }
     }
+
+    best
+}
+
+fn bin_index(object_bbox: &Aabb, centroid_bounds: &Aabb, axis: usize) -> usize {
+    let extent = centroid_bounds.axis(axis);
+    let c = object_bbox.axis(axis);
+    let offset = (0.5 * (c.min + c.max) - extent.min) / extent.size();
+    ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
+
+fn bounds(objects: &[(Aabb, Box<dyn Hittable>)]) -> Aabb {
+    objects.iter().fold(Aabb::EMPTY, |bbox, (object_bbox, _)| {
+        Aabb::new_from_boxes(bbox, *object_bbox)
+    })
 }
 
 impl Hittable for Bvh {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
-        if !self.bbox.hit(r, ray_t) {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        if self.nodes.is_empty() {
             return None;
         }
 
-        match &self.tree {
-            BvhNode::Leaf(object) => object.hit(r, ray_t),
-            BvhNode::Branch { left, right } => {
-                let hit_left = left.hit(r, ray_t);
+        let origin = r.origin();
+        let dir = r.direction();
+        let inv_dir = Vec3::new(1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z());
+        let dir_is_neg = [dir.x() < 0.0, dir.y() < 0.0, dir.z() < 0.0];
 
-                let r_t = Interval::new(
-                    ray_t.min,
-                    if let Some(max) = &hit_left {
-                        max.t
-                    } else {
-                        ray_t.max
-                    },
-                );
+        let mut closest = None;
+        let mut r_t = *ray_t;
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut index = 0;
 
-                right.hit(r, &r_t).or(hit_left)
+        loop {
+            let node = &self.nodes[index];
+            if node.bbox.hit_with_inverse(origin, inv_dir, &r_t) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for object in &self.primitives[first..first + node.count as usize] {
+                        if let Some(rec) = object.hit(r, &r_t) {
+                            r_t.max = rec.t;
+                            closest = Some(rec);
+                        }
+                    }
+                } else {
+                    // Visit the child on the near side of the split first, so
+                    // that a close hit lets the far one be culled.
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, index + 1)
+                    } else {
+                        (index + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    index = near;
+                    continue;
+                }
+            }
 
+            if stack_len == 0 {
+                break;
             }
+            stack_len -= 1;
+            index = stack[stack_len];
         }
+
+        closest
     }
 
     fn bounding_box(&self) -> Option<Aabb> {
-        Some(self.bbox)
+        Some(self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox))
     }
-}
 
-fn box_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>, axis_index: usize) -> Ordering {
-    a.bounding_box()
-        .unwrap()
-        .axis(axis_index)
-        .min
-        .partial_cmp(&b.bounding_box().unwrap().axis(axis_index).min)
-        .unwrap()
-}
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.primitives.is_empty() {
+            return 0.0;
+        }
 
-fn box_compare_x(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 0)
-}
+        let weight = 1.0 / self.primitives.len() as f64;
+        self.primitives
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        if self.primitives.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
 
-fn box_compare_y(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 1)
+        let size = self.primitives.len();
+        let index = ((rng() * size as f64) as usize).min(size - 1);
+        self.primitives[index].random(origin, rng)
+    }
 }
 
-fn box_compare_z(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 2)
+fn box_compare(a: &Aabb, b: &Aabb, axis_index: usize) -> Ordering {
+    a.axis(axis_index)
+        .min
+        .partial_cmp(&b.axis(axis_index).min)
+        .unwrap()
 }
@@ -1,38 +1,163 @@
 extern crate rayon;
 
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
 use rayon::prelude::*;
 
 use crate::{
+    aov::Aov,
+    checkpoint::{self, Checkpoint},
     color::Color,
-    hittable::Hittable,
+    denoise,
+    framebuffer::FrameBuffer,
+    hittable::{HitRecord, Hittable},
     interval::Interval,
+    lens::{Aperture, LensSystem},
+    material::Scatter,
+    pdf::{HittablePdf, MixturePdf, Pdf},
     ray::{Point3, Ray},
-    rtweekend::{self, degrees_to_radians, random_double},
+    rtweekend::{self, degrees_to_radians, PI},
+    sampler::{Sampler, SamplerKind},
+    shutter::{Shutter, ShutterCurve},
+    tiles::{self, Region, TileOrder},
+    tonemap::ToneMapping,
     vec3::Vec3,
 };
 
+/// How [`Camera`] estimates the light arriving along each ray.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Follows one scattered direction per bounce, drawn from the material
+    /// or, when lights are given, from an even mixture with the lights.
+    #[default]
+    Naive,
+    /// Next-event estimation: also samples a light at every diffuse bounce
+    /// and weights both strategies with the power heuristic.
+    NextEvent,
+}
+
+/// How [`Camera`] maps pixels to rays.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// Rays fan out from `lookfrom` across `vfov`.
+    #[default]
+    Perspective,
+    /// Parallel rays along the view direction, starting on the plane
+    /// through `lookfrom`, across a viewport `ortho_height` high.
+    Orthographic,
+    /// All directions around `lookfrom`, with longitude across the image and
+    /// latitude down it. The view direction is in the middle.
+    Equirectangular,
+    /// A circle as wide as the shorter side of the image that covers
+    /// `fisheye_fov`, with the angle from the view direction growing
+    /// linearly with the distance from its center.
+    AngularFisheye,
+    /// Like [`Projection::AngularFisheye`], but the circle keeps the relative
+    /// areas of the solid angles it shows.
+    EquisolidFisheye,
+    /// The six square faces of a cube around `lookfrom` side by side, each
+    /// `image_width / 6` pixels wide: right, left, up, down, front and back.
+    /// `image_width` should be a multiple of 6, as the CLI and scene files
+    /// require, or the leftover columns look past the edge of the back face.
+    /// `aspect_ratio` is ignored.
+    Cubemap,
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
+    /// Samples taken in every pixel, or the most any pixel gets when
+    /// `noise_threshold` enables adaptive sampling.
     pub samples_per_pixel: i32,
+    /// Samples every pixel gets before adaptive sampling may stop it.
+    pub min_samples_per_pixel: i32,
+    /// Relative standard error of a pixel's luminance below which it stops
+    /// being sampled. Zero disables adaptive sampling.
+    pub noise_threshold: f64,
+    /// Renders progressively in passes of this many samples per pixel. Zero
+    /// takes all samples in one pass.
+    pub pass_samples: i32,
+    /// Where the image so far is written after every progressive pass.
+    pub snapshot_path: Option<PathBuf>,
+    /// Where the render state is saved after every pass.
+    /// [`Camera::resume`] continues from it.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Identifies the scene in checkpoints, which only resume renders of the
+    /// same scene id and camera settings.
+    pub scene_id: u64,
+    /// Hard limit on the bounces of a path. Most paths end earlier by
+    /// Russian roulette.
     pub max_depth: i32,
+    /// Bounces every path takes before Russian roulette may end it.
+    pub min_bounces: i32,
     pub vfov: f64,
+    pub projection: Projection,
+    /// Height in world units of what the orthographic projection shows.
+    /// Zero takes the height that `vfov` covers at `lookat`, so that the
+    /// perspective and orthographic views frame it alike.
+    pub ortho_height: f64,
+    /// Field of view of the fisheye projections in degrees, up to 360.
+    pub fisheye_fov: f64,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// Shape of the lens opening, which out of focus highlights take on.
+    pub aperture: Aperture,
+    /// How much of the lens opening the lens barrel hides from the edges of
+    /// the image, which gives out of focus highlights there the shape of a
+    /// cat's eye. Zero leaves the opening whole; at one little more than a
+    /// third of it is left in the corners.
+    pub cat_eye: f64,
+    /// Traces the perspective projection through this lens instead of an
+    /// ideal thin lens. `vfov` frames the view for a lens focused far away
+    /// and `focus_dist` focuses it; the exit pupil of the lens takes the
+    /// shape of `aperture`, and the lens's own stop and barrel take the
+    /// place of `defocus_angle` and `cat_eye`.
+    pub lens: Option<Arc<LensSystem>>,
+    /// When the rays of each pixel are sent, which blurs moving objects.
+    pub shutter: Shutter,
     pub background: Color,
+    pub seed: u64,
+    pub integrator: Integrator,
+    /// Generates the pixel, lens, time and bounce dimensions of each sample.
+    pub sampler: SamplerKind,
+    /// Width and height of the tiles the image is rendered in.
+    pub tile_size: i32,
+    pub tile_order: TileOrder,
+    /// Only renders the pixels in this region; the rest of the image stays
+    /// black.
+    pub crop: Option<Region>,
+    /// Output variables of the first hits to render along with the image.
+    pub aovs: Vec<Aov>,
+    /// Filters the finished image with [`denoise::denoise`].
+    pub denoise: bool,
+    /// Exposure and tone mapper of the 8-bit images made of the render.
+    pub tone_mapping: ToneMapping,
     u: Vec3,
     v: Vec3,
     w: Vec3,
     image_height: i32,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    /// Axial position of the film behind `lens`, in millimetres.
+    film_position: f64,
+    /// Half the width and height of the film.
+    film_size: (f64, f64),
+    exit_pupil: f64,
     center: Point3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
+    resumed: Option<Vec<PixelState>>,
 }
 
 impl Default for Camera {
@@ -42,8 +167,18 @@ impl Default for Camera {
             image_width: 100,
             image_height: 0,
             samples_per_pixel: 10,
-            max_depth: 10,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.0,
+            pass_samples: 0,
+            snapshot_path: None,
+            checkpoint_path: None,
+            scene_id: 0,
+            max_depth: 50,
+            min_bounces: 3,
             vfov: 90.0,
+            projection: Projection::default(),
+            ortho_height: 0.0,
+            fisheye_fov: 180.0,
             lookfrom: Point3::new(0.0, 0.0, -1.0),
             lookat: Point3::new(0.0, 0.0, 0.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
@@ -58,57 +193,710 @@ impl Default for Camera {
             focus_dist: 10.0,
             defocus_disk_u: Vec3::default(),
             defocus_disk_v: Vec3::default(),
+            aperture: Aperture::default(),
+            cat_eye: 0.0,
+            lens: None,
+            shutter: Shutter::default(),
+            film_position: 0.0,
+            film_size: (0.0, 0.0),
+            exit_pupil: 0.0,
             background: Color::default(),
+            seed: 0,
+            integrator: Integrator::default(),
+            sampler: SamplerKind::default(),
+            tile_size: 16,
+            tile_order: TileOrder::default(),
+            crop: None,
+            aovs: Vec::new(),
+            denoise: false,
+            tone_mapping: ToneMapping::default(),
+            resumed: None,
         }
     }
 }
 
 impl Camera {
-    pub fn render(&mut self, world: &dyn Hittable) -> Vec<(i32, i32, i32)> {
+    pub fn render(&mut self, world: &dyn Hittable) -> FrameBuffer {
+        self.render_impl(world, None)
+    }
+
+    /// Renders `world`, importance sampling the emitters in `lights` at every
+    /// diffuse bounce alongside the materials' own scattering densities.
+    /// `lights` only needs the light geometry; its materials are ignored.
+    pub fn render_with_lights(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+    ) -> FrameBuffer {
+        self.render_impl(world, Some(lights))
+    }
+
+    /// Loads the checkpoint at `checkpoint_path`, if there is one, so that
+    /// the next render adds to its samples instead of starting over. Returns
+    /// the mean number of samples per pixel it holds.
+    ///
+    /// `samples_per_pixel`, `noise_threshold` and the progressive settings
+    /// may differ from the interrupted render, except that the stratified
+    /// sampler needs the same `samples_per_pixel`. Everything else that
+    /// affects the image has to match.
+    pub fn resume(&mut self) -> io::Result<Option<f64>> {
+        let Some(path) = &self.checkpoint_path else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let checkpoint = Checkpoint::read(path)?;
+        self.initialize();
+        if checkpoint.fingerprint != self.fingerprint()
+            || checkpoint.width != self.image_width as u32
+            || checkpoint.height != self.image_height as u32
+        {
+            return Err(checkpoint::invalid_data(
+                "the checkpoint is of a different scene or camera",
+            ));
+        }
+
+        let samples = checkpoint
+            .pixels
+            .iter()
+            .map(|p| p.luminance.count as u64)
+            .sum::<u64>();
+        let mean = samples as f64 / checkpoint.pixels.len().max(1) as f64;
+        self.resumed = Some(checkpoint.pixels);
+        Ok(Some(mean))
+    }
+
+    fn render_impl(&mut self, world: &dyn Hittable, lights: Option<&dyn Hittable>) -> FrameBuffer {
         self.initialize();
-        println!("P3\n{} {}\n255", self.image_width, self.image_height);
         eprintln!("\rProcessing...");
-        let collection = (0..self.image_height)
+
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let max_samples = self.samples_per_pixel.max(0) as u32;
+        let adaptive = self.noise_threshold > 0.0;
+        let mut pixels = self
+            .resumed
+            .take()
+            .unwrap_or_else(|| vec![PixelState::default(); width * height]);
+
+        // With adaptive sampling every pixel gets `min_samples_per_pixel`
+        // samples, then pixels near ones that have not converged get that
+        // many more per pass. Judging a whole neighbourhood keeps pixels from
+        // stopping on a lucky run of similar samples.
+        let pass_samples = if self.pass_samples > 0 {
+            self.pass_samples as u32
+        } else if adaptive {
+            self.min_samples_per_pixel.max(2) as u32
+        } else if self.checkpoint_path.is_some() {
+            CHECKPOINT_PASS_SAMPLES
+        } else {
+            max_samples
+        };
+
+        let mut region = Region::new(0, 0, width, height);
+        if let Some(crop) = &self.crop {
+            region = region.intersect(crop);
+        }
+        let tiles = tiles::tiles(&region, self.tile_size.max(1) as usize, self.tile_order);
+
+        let active_pixels = |pixels: &[PixelState]| {
+            if adaptive {
+                self.unconverged_neighbourhoods(pixels, &region)
+            } else {
+                pixels
+                    .iter()
+                    .enumerate()
+                    .map(|(pixel, p)| {
+                        region.contains(pixel % width, pixel / width)
+                            && p.luminance.count < max_samples
+                    })
+                    .collect()
+            }
+        };
+
+        let mut active = active_pixels(&pixels);
+        let mut pass = 0;
+        while active.iter().any(|&a| a) {
+            self.sample_pixels(&mut pixels, &active, &tiles, pass_samples, world, lights);
+            active = active_pixels(&pixels);
+
+            pass += 1;
+            if self.pass_samples > 0 {
+                self.write_snapshot(&pixels, pass);
+            }
+            if let Some(path) = &self.checkpoint_path {
+                self.write_checkpoint(path, &pixels);
+            }
+        }
+
+        let image = self.frame_buffer(&pixels);
+        let mut aovs = self.aovs.clone();
+        if self.denoise {
+            aovs.extend(
+                denoise::GUIDES
+                    .iter()
+                    .filter(|aov| !self.aovs.contains(aov)),
+            );
+        }
+        if aovs.is_empty() {
+            return image;
+        }
+
+        let image = image.with_aovs(self.render_aovs(&aovs, &region, world));
+        if !self.denoise {
+            return image;
+        }
+        // Only the output variables that were asked for are kept.
+        let mut aovs = image.aovs().to_vec();
+        aovs.retain(|(aov, _)| self.aovs.contains(aov));
+        denoise::denoise(&image).unwrap().with_aovs(aovs)
+    }
+
+    fn frame_buffer(&self, pixels: &[PixelState]) -> FrameBuffer {
+        let variances = pixels.iter().map(|p| p.luminance.mean_variance()).collect();
+        let (pixels, sample_counts) = pixels
+            .iter()
+            .map(|p| {
+                // Pixels that have not been sampled yet stay black.
+                let count = p.luminance.count;
+                (p.sum / count.max(1) as f64, count)
+            })
+            .unzip();
+        FrameBuffer::from_pixels(
+            self.image_width as usize,
+            self.image_height as usize,
+            pixels,
+        )
+        .with_sample_counts(sample_counts)
+        .with_variances(variances)
+        .with_tone_mapping(self.tone_mapping)
+    }
+
+    fn write_checkpoint(&self, path: &Path, pixels: &[PixelState]) {
+        let result = Checkpoint::write(
+            path,
+            self.fingerprint(),
+            self.image_width as u32,
+            self.image_height as u32,
+            pixels,
+        );
+        if let Err(e) = result {
+            eprintln!(
+                "ERROR: Could not write checkpoint \"{}\": {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Hash of the scene id and of the camera settings that a resumed render
+    /// has to share with the checkpoint.
+    fn fingerprint(&self) -> u64 {
+        let mut fields = vec![
+            self.scene_id,
+            self.seed,
+            self.image_width as u64,
+            self.image_height as u64,
+            self.max_depth as u64,
+            self.min_bounces as u64,
+            self.integrator as u64,
+            self.sampler as u64,
+            self.vfov.to_bits(),
+            self.defocus_angle.to_bits(),
+            self.focus_dist.to_bits(),
+        ];
+        if self.projection != Projection::Perspective {
+            fields.extend([
+                self.projection as u64,
+                self.ortho_height.to_bits(),
+                self.fisheye_fov.to_bits(),
+            ]);
+        }
+        fields.extend(self.aperture.fingerprint());
+        if self.shutter != Shutter::default() {
+            let curve = match &self.shutter.curve {
+                ShutterCurve::Box => vec![0],
+                ShutterCurve::Triangle => vec![1],
+                ShutterCurve::Custom(weights) => std::iter::once(2)
+                    .chain(weights.iter().map(|w| w.to_bits()))
+                    .collect(),
+            };
+            fields.extend([
+                self.shutter.open.to_bits(),
+                self.shutter.close.to_bits(),
+                self.shutter.rolling.to_bits(),
+            ]);
+            fields.extend(curve);
+        }
+        if self.cat_eye > 0.0 {
+            fields.push(self.cat_eye.to_bits());
+        }
+        if let Some(lens) = &self.lens {
+            fields.push(lens.scale.to_bits());
+            for e in lens.elements() {
+                fields.extend([
+                    e.radius.to_bits(),
+                    e.thickness.to_bits(),
+                    e.ior.to_bits(),
+                    e.aperture.to_bits(),
+                ]);
+            }
+        }
+        // The strata of the stratified sampler depend on the sample count.
+        if self.sampler == SamplerKind::Stratified {
+            fields.push(self.samples_per_pixel as u64);
+        }
+        for v in [self.lookfrom, self.lookat, self.vup, self.background] {
+            fields.extend([v.x().to_bits(), v.y().to_bits(), v.z().to_bits()]);
+        }
+        rtweekend::mix_seed(0, &fields)
+    }
+
+    fn write_snapshot(&self, pixels: &[PixelState], pass: u32) {
+        let samples = pixels.iter().map(|p| p.luminance.count as u64).sum::<u64>();
+        eprintln!(
+            "\rPass {}: {:.1} samples per pixel",
+            pass,
+            samples as f64 / pixels.len().max(1) as f64
+        );
+
+        let Some(path) = &self.snapshot_path else {
+            return;
+        };
+        if let Err(e) = self.frame_buffer(pixels).save(path) {
+            eprintln!(
+                "ERROR: Could not write snapshot \"{}\": {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Adds up to `samples` more samples to every active pixel, without going
+    /// over `samples_per_pixel`. The render threads take the tiles in order.
+    fn sample_pixels(
+        &self,
+        pixels: &mut [PixelState],
+        active: &[bool],
+        tiles: &[Region],
+        samples: u32,
+        world: &dyn Hittable,
+        lights: Option<&dyn Hittable>,
+    ) {
+        let next_tile = AtomicUsize::new(0);
+        let finished = Mutex::new(Vec::with_capacity(tiles.len()));
+
+        let current: &[PixelState] = pixels;
+        (0..rayon::current_num_threads())
             .into_par_iter()
-            .map(|j| {
-                (0..self.image_width)
-                    .into_par_iter()
+            .for_each(|_| {
+                while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                    let states = self.sample_tile(tile, current, active, samples, world, lights);
+                    finished.lock().unwrap().push((tile, states));
+                }
+            });
+
+        let width = self.image_width as usize;
+        for (tile, states) in finished.into_inner().unwrap() {
+            for (row, states) in (tile.y0..tile.y1).zip(states.chunks(tile.width())) {
+                pixels[row * width + tile.x0..row * width + tile.x1].clone_from_slice(states);
+            }
+        }
+    }
+
+    /// The states of the pixels in `tile` after sampling, row by row.
+    fn sample_tile(
+        &self,
+        tile: &Region,
+        pixels: &[PixelState],
+        active: &[bool],
+        samples: u32,
+        world: &dyn Hittable,
+        lights: Option<&dyn Hittable>,
+    ) -> Vec<PixelState> {
+        let width = self.image_width as usize;
+        let max_samples = self.samples_per_pixel.max(0) as u32;
+        let mut sampler = self.sampler.build(self.seed, max_samples);
+        let sampler = sampler.as_mut();
+        let mut states = Vec::with_capacity(tile.width() * tile.height());
+
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let pixel = j * width + i;
+                let mut state = pixels[pixel].clone();
+                if !active[pixel] {
+                    states.push(state);
+                    continue;
+                }
+
+                let start = state.luminance.count;
+                for sample in start..(start + samples).min(max_samples) {
+                    rtweekend::seed(rtweekend::mix_seed(
+                        self.seed,
+                        &[pixel as u64, sample as u64],
+                    ));
+                    sampler.start_sample(pixel as u64, sample);
+
+                    // Samples outside of a fisheye's circle are black.
+                    let color = match self.get_ray(i as i32, j as i32, sampler) {
+                        None => Color::default(),
+                        Some(r) => match self.integrator {
+                            Integrator::Naive => self.ray_color(&r, world, lights, sampler),
+                            Integrator::NextEvent => self.ray_color_nee(&r, world, lights, sampler),
+                        },
+                    };
+                    state.sum += color;
+                    state.luminance.push(color.luminance());
+                }
+                states.push(state);
+            }
+        }
+
+        states
+    }
+
+    /// Pixels in `region` that may take more samples and have a pixel of the
+    /// region in their 3x3 neighbourhood whose noise is above
+    /// `noise_threshold`.
+    fn unconverged_neighbourhoods(&self, pixels: &[PixelState], region: &Region) -> Vec<bool> {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let max_samples = self.samples_per_pixel.max(0) as u32;
+
+        let min_samples = self.min_samples_per_pixel.max(0) as u32;
+        let noisy = pixels
+            .iter()
+            .enumerate()
+            .map(|(pixel, p)| {
+                region.contains(pixel % width, pixel / width)
+                    && (p.luminance.count < min_samples
+                        || p.luminance.relative_error() >= self.noise_threshold)
+            })
+            .collect::<Vec<_>>();
+
+        (0..width * height)
+            .map(|pixel| {
+                let (i, j) = (pixel % width, pixel / width);
+                if !region.contains(i, j) || pixels[pixel].luminance.count >= max_samples {
+                    return false;
+                }
+
+                (j.saturating_sub(1)..(j + 2).min(height)).any(|y| {
+                    (i.saturating_sub(1)..(i + 2).min(width)).any(|x| noisy[y * width + x])
+                })
+            })
+            .collect()
+    }
+
+    /// Traces the first `AOV_SAMPLES` camera rays of every pixel in `region`
+    /// again and averages what they hit.
+    fn render_aovs(
+        &self,
+        aovs: &[Aov],
+        region: &Region,
+        world: &dyn Hittable,
+    ) -> Vec<(Aov, FrameBuffer)> {
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let max_samples = self.samples_per_pixel.max(1) as u32;
+        let samples = AOV_SAMPLES.min(max_samples);
+
+        let first_hits = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|j| {
+                let mut sampler = self.sampler.build(self.seed, max_samples);
+                (0..width)
                     .map(|i| {
-                        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                        for _ in 0..self.samples_per_pixel {
-                            let r = self.get_ray(i, j);
-                            pixel_color += self.ray_color(&r, self.max_depth, world);
+                        if !region.contains(i, j) {
+                            return FirstHit::default();
                         }
-                        let (x, y, z) = pixel_color.color(self.samples_per_pixel);
-                        (x, y, z)
+                        self.first_hit(i, j, samples, world, sampler.as_mut())
                     })
                     .collect::<Vec<_>>()
             })
             .collect::<Vec<_>>();
 
-        collection.into_iter().flatten().collect::<Vec<_>>()
+        aovs.iter()
+            .map(|&aov| {
+                let pixels = first_hits.iter().map(|hit| hit.get(aov)).collect();
+                (aov, FrameBuffer::from_pixels(width, height, pixels))
+            })
+            .collect()
     }
 
-    fn ray_color(&self, r: &Ray, depth: i32, world: &dyn Hittable) -> Color {
-        if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+    /// Uses the seeds of the image's samples, so the output variables line up
+    /// with its edges and defocus blur.
+    fn first_hit(
+        &self,
+        i: usize,
+        j: usize,
+        samples: u32,
+        world: &dyn Hittable,
+        sampler: &mut dyn Sampler,
+    ) -> FirstHit {
+        let pixel = j * self.image_width as usize + i;
+        let mut first = FirstHit::default();
+        let mut hits = 0;
+
+        for sample in 0..samples {
+            rtweekend::seed(rtweekend::mix_seed(
+                self.seed,
+                &[pixel as u64, sample as u64],
+            ));
+            sampler.start_sample(pixel as u64, sample);
+
+            let Some(r) = self.get_ray(i as i32, j as i32, sampler) else {
+                continue;
+            };
+            let Some(rec) = world.hit(&r, &Interval::new(0.001, rtweekend::INFINITY)) else {
+                first.albedo += self.background;
+                continue;
+            };
+
+            // The ids cannot be averaged, so they come from the first sample
+            // that hits anything.
+            if hits == 0 {
+                first.object_id = rec.object_id;
+                first.material_id = rec.mat.id();
+            }
+            hits += 1;
+            first.depth += rec.t
+                * match self.projection {
+                    Projection::Perspective | Projection::Orthographic => {
+                        Vec3::dot(r.direction(), -self.w)
+                    }
+                    // Panoramas have no single view direction.
+                    _ => r.direction().length(),
+                };
+            first.normal += rec.normal;
+            first.albedo += rec.mat.albedo(&rec);
+            first.position += rec.p;
+            first.uv += Vec3::new(rec.u, rec.v, 0.0);
         }
 
-        if let Some(rec) = world.hit(r, &Interval::new(0.001, rtweekend::INFINITY)) {
-            let color_from_emission = rec.mat.emitted(rec.u, rec.v, rec.p);
-            if let Some((scatterd, attenuation)) = rec.mat.scatter(r, &rec) {
-                let color_from_scatter = attenuation * self.ray_color(&scatterd, depth - 1, world);
-                color_from_emission + color_from_scatter
-            } else {
-                color_from_emission
+        let n = hits.max(1) as f64;
+        FirstHit {
+            depth: first.depth / n,
+            normal: first.normal / n,
+            albedo: first.albedo / samples.max(1) as f64,
+            position: first.position / n,
+            uv: first.uv / n,
+            ..first
+        }
+    }
+
+    fn ray_color(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: Option<&dyn Hittable>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+
+        for bounce in 0..self.max_depth {
+            let mut dimensions = sampler.start_bounce();
+            let rng = &mut || dimensions.get_1d();
+            let Some(rec) = world.hit(&ray, &Interval::new(0.001, rtweekend::INFINITY)) else {
+                color += throughput * self.background;
+                break;
+            };
+
+            color += throughput * rec.mat.emitted(rec.u, rec.v, rec.p);
+            let Some(srec) = rec.mat.scatter(&ray, &rec, rng) else {
+                break;
+            };
+
+            match srec.scatter {
+                Scatter::Specular(scattered) => {
+                    throughput = throughput * srec.attenuation;
+                    ray = scattered;
+                }
+                Scatter::Pdf(material_pdf) => {
+                    // Half of the directions go towards the lights when they
+                    // can be sampled.
+                    let light_sample = lights.and_then(|lights| {
+                        let light_pdf = HittablePdf::new(lights, rec.p);
+                        let direction = light_pdf.generate(rng);
+                        (light_pdf.value(direction) > 0.0).then_some((light_pdf, direction))
+                    });
+                    let (direction, pdf_value) = match light_sample {
+                        Some((light_pdf, light_direction)) => {
+                            let mixture = MixturePdf::new(&light_pdf, material_pdf.as_ref());
+                            let direction = if rng() < 0.5 {
+                                light_direction
+                            } else {
+                                material_pdf.generate(rng)
+                            };
+                            (direction, mixture.value(direction))
+                        }
+                        None => {
+                            let direction = material_pdf.generate(rng);
+                            (direction, material_pdf.value(direction))
+                        }
+                    };
+                    if pdf_value <= 0.0 {
+                        break;
+                    }
+
+                    let scattered = Ray::new_with_time(rec.p, direction, ray.time());
+                    let scattering_pdf = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+                    throughput = throughput * srec.attenuation * scattering_pdf / pdf_value;
+                    ray = scattered;
+                }
+            }
+
+            if !self.survives_roulette(bounce, &mut throughput, rng) {
+                break;
             }
-        } else {
-            self.background
         }
+
+        color
+    }
+
+    fn ray_color_nee(
+        &self,
+        r: &Ray,
+        world: &dyn Hittable,
+        lights: Option<&dyn Hittable>,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        // Density with which the material at the previous bounce sampled
+        // `ray`, or `None` when it comes from the camera or a specular bounce
+        // and so could not have been found by light sampling.
+        let mut bsdf_pdf = None;
+
+        for bounce in 0..self.max_depth {
+            let mut dimensions = sampler.start_bounce();
+            let rng = &mut || dimensions.get_1d();
+            let Some(rec) = world.hit(&ray, &Interval::new(0.001, rtweekend::INFINITY)) else {
+                color += throughput * self.background;
+                break;
+            };
+
+            let mut emitted = rec.mat.emitted(rec.u, rec.v, rec.p);
+            if let (Some(bsdf_pdf), Some(lights)) = (bsdf_pdf, lights) {
+                let light_pdf = lights.pdf_value(ray.origin(), ray.direction());
+                emitted = power_heuristic(bsdf_pdf, light_pdf) * emitted;
+            }
+            color += throughput * emitted;
+
+            let Some(srec) = rec.mat.scatter(&ray, &rec, rng) else {
+                break;
+            };
+
+            match srec.scatter {
+                Scatter::Specular(scattered) => {
+                    throughput = throughput * srec.attenuation;
+                    ray = scattered;
+                    bsdf_pdf = None;
+                }
+                Scatter::Pdf(material_pdf) => {
+                    if let Some(lights) = lights {
+                        color += throughput
+                            * srec.attenuation
+                            * self.sample_light(
+                                &ray,
+                                &rec,
+                                material_pdf.as_ref(),
+                                world,
+                                lights,
+                                rng,
+                            );
+                    }
+
+                    let direction = material_pdf.generate(rng);
+                    let pdf_value = material_pdf.value(direction);
+                    if pdf_value <= 0.0 {
+                        break;
+                    }
+
+                    let scattered = Ray::new_with_time(rec.p, direction, ray.time());
+                    let scattering_pdf = rec.mat.scattering_pdf(&ray, &rec, &scattered);
+                    throughput = throughput * srec.attenuation * scattering_pdf / pdf_value;
+                    ray = scattered;
+                    bsdf_pdf = Some(pdf_value);
+                }
+            }
+
+            if !self.survives_roulette(bounce, &mut throughput, rng) {
+                break;
+            }
+        }
+
+        color
+    }
+
+    /// Russian roulette: once `min_bounces` are done, ends the path with a
+    /// probability that grows as its throughput drops, and boosts the paths
+    /// that survive so that the estimate stays unbiased.
+    fn survives_roulette(
+        &self,
+        bounce: i32,
+        throughput: &mut Color,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> bool {
+        if bounce + 1 < self.min_bounces {
+            return true;
+        }
+
+        let p = throughput
+            .x()
+            .max(throughput.y())
+            .max(throughput.z())
+            .min(0.95);
+        if p <= 0.0 || rng() >= p {
+            return false;
+        }
+        *throughput /= p;
+        true
+    }
+
+    /// Light arriving at `rec` from one direction towards `lights`, weighted
+    /// against the chance of `material_pdf` sampling the same direction.
+    fn sample_light(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        material_pdf: &dyn Pdf,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Color {
+        let direction = lights.random(rec.p, rng);
+        let light_pdf = lights.pdf_value(rec.p, direction);
+        if light_pdf <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let shadow_ray = Ray::new_with_time(rec.p, direction, r.time());
+        let scattering_pdf = rec.mat.scattering_pdf(r, rec, &shadow_ray);
+        if scattering_pdf <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let Some(light_rec) = world.hit(&shadow_ray, &Interval::new(0.001, rtweekend::INFINITY))
+        else {
+            return Color::new(0.0, 0.0, 0.0);
+        };
+        let emitted = light_rec.mat.emitted(light_rec.u, light_rec.v, light_rec.p);
+
+        let weight = power_heuristic(light_pdf, material_pdf.value(direction));
+        emitted * scattering_pdf * weight / light_pdf
     }
 
     fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
+        self.image_height = match self.projection {
+            Projection::Cubemap => self.image_width / 6,
+            _ => (self.image_width as f64 / self.aspect_ratio) as i32,
+        };
         self.image_height = if self.image_height < 1 {
             1
         } else {
@@ -120,7 +908,11 @@ impl Camera {
         let theta = degrees_to_radians(self.vfov);
         let h = theta / 2.0;
         let h = h.tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
+        let viewport_height = match self.projection {
+            Projection::Orthographic if self.ortho_height > 0.0 => self.ortho_height,
+            Projection::Orthographic => 2.0 * h * (self.lookat - self.lookfrom).length(),
+            _ => 2.0 * h * self.focus_dist,
+        };
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         self.w = Vec3::unit_vector(self.lookfrom - self.lookat);
@@ -142,35 +934,241 @@ impl Camera {
 
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        if let Some(lens) = &self.lens {
+            let film_distance = lens.film_distance(self.focus_dist);
+            let half_height = lens.focal_length() * h;
+            self.film_position = lens.length() + film_distance;
+            self.film_size = (half_height * viewport_width / viewport_height, half_height);
+            self.exit_pupil = lens.exit_pupil(film_distance);
+        }
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let pixel_center =
-            self.pixel00_loc + i as f64 * self.pixel_delta_u + j as f64 * self.pixel_delta_v;
+    /// A ray through a random point of pixel `i, j`, or `None` if the point
+    /// shows nothing.
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let offset = sampler.get_2d();
+        let (x, y) = (i as f64 + offset.0, j as f64 + offset.1);
 
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let (ray_origin, ray_direction) = match self.projection {
+            Projection::Perspective if self.lens.is_some() => {
+                self.lens_ray(x, y, sampler.get_2d())?
+            }
+            Projection::Perspective | Projection::Orthographic => {
+                let pixel_center = self.pixel00_loc
+                    + i as f64 * self.pixel_delta_u
+                    + j as f64 * self.pixel_delta_v;
+                let pixel_sample = pixel_center + self.pixel_sample_square(offset);
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+                let ray_origin = match self.projection {
+                    // Straight back from the pixel to the plane of the camera.
+                    Projection::Orthographic => pixel_sample + self.focus_dist * self.w,
+                    _ => self.center,
+                };
+                let ray_origin = if self.defocus_angle <= 0.0 {
+                    ray_origin
+                } else {
+                    ray_origin + self.defocus_disk_sample(x, y, sampler.get_2d())?
+                };
+                (ray_origin, pixel_sample - ray_origin)
+            }
+            Projection::Equirectangular => (self.center, self.equirectangular_direction(x, y)),
+            Projection::AngularFisheye | Projection::EquisolidFisheye => {
+                (self.center, self.fisheye_direction(x, y)?)
+            }
+            Projection::Cubemap => (self.center, self.cubemap_direction(x, y)),
+        };
+        let ray_time = self
+            .shutter
+            .time(y / self.image_height as f64, sampler.get_1d());
+
+        Some(Ray::new_with_time(ray_origin, ray_direction, ray_time))
+    }
+
+    fn equirectangular_direction(&self, x: f64, y: f64) -> Vec3 {
+        let phi = 2.0 * PI * (x / self.image_width as f64 - 0.5);
+        let theta = PI * (0.5 - y / self.image_height as f64);
+        theta.cos() * (phi.sin() * self.u - phi.cos() * self.w) + theta.sin() * self.v
+    }
+
+    fn fisheye_direction(&self, x: f64, y: f64) -> Option<Vec3> {
+        let (width, height) = (self.image_width as f64, self.image_height as f64);
+        let radius = 0.5 * width.min(height);
+        let (px, py) = ((x - 0.5 * width) / radius, (0.5 * height - y) / radius);
+        let r = (px * px + py * py).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let half_fov = degrees_to_radians(self.fisheye_fov.min(360.0)) / 2.0;
+        let theta = match self.projection {
+            Projection::EquisolidFisheye => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+            _ => r * half_fov,
+        };
+        let (sx, sy) = if r > 0.0 {
+            (px / r, py / r)
         } else {
-            self.defocus_disk_sample()
+            (0.0, 0.0)
         };
+        Some(theta.sin() * (sx * self.u + sy * self.v) - theta.cos() * self.w)
+    }
 
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random_double();
+    fn cubemap_direction(&self, x: f64, y: f64) -> Vec3 {
+        let size = self.image_height as f64;
+        let face = ((x / size) as usize).min(5);
+        let a = 2.0 * (x / size - face as f64) - 1.0;
+        let b = 1.0 - 2.0 * y / size;
 
-        Ray::new_with_time(ray_origin, ray_direction, ray_time)
+        // The forward, right and up directions of each face.
+        let (u, v, w) = (self.u, self.v, self.w);
+        let (forward, right, up) = match face {
+            0 => (u, w, v),
+            1 => (-u, -w, v),
+            2 => (v, u, w),
+            3 => (-v, u, -w),
+            4 => (-w, u, v),
+            _ => (w, -u, v),
+        };
+        forward + a * right + b * up
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + rtweekend::random_double();
-        let py = -0.5 + rtweekend::random_double();
+    fn pixel_sample_square(&self, (u, v): (f64, f64)) -> Vec3 {
+        let px = -0.5 + u;
+        let py = -0.5 + v;
         (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
+    /// Offset of a random point on the lens from its center, or `None` if
+    /// the lens barrel hides it from image position `x, y`.
+    fn defocus_disk_sample(&self, x: f64, y: f64, u: (f64, f64)) -> Option<Vec3> {
+        let (lx, ly) = self.aperture.sample(u);
+
+        if self.cat_eye > 0.0 {
+            // The barrel is a second opening, shifted towards the edge of the
+            // image.
+            let (width, height) = (self.image_width as f64, self.image_height as f64);
+            let half_diagonal = 0.5 * width.hypot(height);
+            let dx = lx - self.cat_eye * (x - 0.5 * width) / half_diagonal;
+            let dy = ly - self.cat_eye * (0.5 * height - y) / half_diagonal;
+            if dx * dx + dy * dy > 1.0 {
+                return None;
+            }
+        }
+
+        Some((lx * self.defocus_disk_u) + (ly * self.defocus_disk_v))
+    }
+
+    /// Origin and direction of a ray from image position `x, y` through a
+    /// random point of the exit pupil of `lens`, or `None` if the lens
+    /// blocks it.
+    fn lens_ray(&self, x: f64, y: f64, u: (f64, f64)) -> Option<(Point3, Vec3)> {
+        let lens = self.lens.as_deref()?;
+        let (width, height) = (self.image_width as f64, self.image_height as f64);
+
+        // The lens turns the image upside down.
+        let film = Vec3::new(
+            (1.0 - 2.0 * x / width) * self.film_size.0,
+            (2.0 * y / height - 1.0) * self.film_size.1,
+            self.film_position,
+        );
+        let (px, py) = self.aperture.sample(u);
+        let pupil = Vec3::new(px * self.exit_pupil, py * self.exit_pupil, lens.length());
+        let (origin, direction) = lens.trace(film, pupil - film)?;
+
+        let to_world = |v: Vec3| v.x() * self.u + v.y() * self.v + v.z() * self.w;
+        Some((
+            self.center + lens.scale * to_world(origin),
+            to_world(direction),
+        ))
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Camera rays per pixel averaged into the output variables.
+const AOV_SAMPLES: u32 = 16;
 
-        self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
+#[derive(Default)]
+struct FirstHit {
+    depth: f64,
+    normal: Vec3,
+    albedo: Color,
+    position: Point3,
+    uv: Vec3,
+    object_id: u32,
+    material_id: u32,
+}
+
+impl FirstHit {
+    fn get(&self, aov: Aov) -> Color {
+        match aov {
+            Aov::Depth => Color::new(self.depth, self.depth, self.depth),
+            Aov::Normal => self.normal,
+            Aov::Albedo => self.albedo,
+            Aov::Position => self.position,
+            Aov::Uv => self.uv,
+            Aov::ObjectId => {
+                let id = self.object_id as f64;
+                Color::new(id, id, id)
+            }
+            Aov::MaterialId => {
+                let id = self.material_id as f64;
+                Color::new(id, id, id)
+            }
+        }
     }
 }
+
+/// Passes of a render that is checkpointed but not progressive.
+const CHECKPOINT_PASS_SAMPLES: u32 = 16;
+
+#[derive(Clone, Default)]
+pub(crate) struct PixelState {
+    pub sum: Color,
+    pub luminance: RunningStats,
+}
+
+/// Running mean and variance with Welford's algorithm.
+#[derive(Clone, Default)]
+pub(crate) struct RunningStats {
+    pub count: u32,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+impl RunningStats {
+    fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Standard error of the mean relative to the mean, which is floored so
+    /// that nearly black pixels do not need an exact zero.
+    fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        self.mean_variance().sqrt() / self.mean.max(MIN_LUMINANCE)
+    }
+
+    /// Variance of the mean. A single sample is taken to be off by as much
+    /// as its own value.
+    fn mean_variance(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            1 => self.mean * self.mean,
+            n => self.m2 / (n - 1) as f64 / n as f64,
+        }
+    }
+}
+
+const MIN_LUMINANCE: f64 = 0.01;
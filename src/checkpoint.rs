@@ -0,0 +1,122 @@
+//! Checkpoint files of renders in progress.
+//!
+//! A checkpoint holds the accumulated color and luminance statistics of every
+//! pixel. Samples are seeded from the camera seed, the pixel and the sample
+//! index, so together with the sample counts that is the whole random number
+//! state: a resumed render gives the same image as one that was never
+//! interrupted.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    camera::{PixelState, RunningStats},
+    color::Color,
+};
+
+const MAGIC: &[u8; 8] = b"RTNWCKPT";
+const VERSION: u32 = 1;
+
+pub(crate) struct Checkpoint {
+    /// Identifies the scene and the camera settings the samples were taken
+    /// with.
+    pub fingerprint: u64,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<PixelState>,
+}
+
+impl Checkpoint {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint file"));
+        }
+        let version = read_u32(&mut r)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported checkpoint version {}",
+                version
+            )));
+        }
+
+        let fingerprint = read_u64(&mut r)?;
+        let width = read_u32(&mut r)?;
+        let height = read_u32(&mut r)?;
+        let pixels = (0..width as usize * height as usize)
+            .map(|_| {
+                let sum = Color::new(read_f64(&mut r)?, read_f64(&mut r)?, read_f64(&mut r)?);
+                let luminance = RunningStats {
+                    count: read_u32(&mut r)?,
+                    mean: read_f64(&mut r)?,
+                    m2: read_f64(&mut r)?,
+                };
+                Ok(PixelState { sum, luminance })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            fingerprint,
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Writes to a temporary file next to `path` first, so that a render
+    /// killed while saving keeps its previous checkpoint.
+    pub fn write(
+        path: &Path,
+        fingerprint: u64,
+        width: u32,
+        height: u32,
+        pixels: &[PixelState],
+    ) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        let mut w = BufWriter::new(File::create(&temp)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&fingerprint.to_le_bytes())?;
+        w.write_all(&width.to_le_bytes())?;
+        w.write_all(&height.to_le_bytes())?;
+        for p in pixels {
+            for c in [p.sum.x(), p.sum.y(), p.sum.z()] {
+                w.write_all(&c.to_le_bytes())?;
+            }
+            w.write_all(&p.luminance.count.to_le_bytes())?;
+            w.write_all(&p.luminance.mean.to_le_bytes())?;
+            w.write_all(&p.luminance.m2.to_le_bytes())?;
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(&temp, path)
+    }
+}
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(r: &mut impl Read) -> io::Result<f64> {
+    read_u64(r).map(f64::from_bits)
+}
@@ -0,0 +1,531 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::Parser;
+
+use rtnw::{
+    bvh::SplitMethod,
+    scene,
+    scene_file::SceneDescription,
+    tiles::{Region, TileOrder},
+    Aov, Aperture, ApertureImage, Camera, Color, ExrPrecision, Integrator, LensSystem, Projection,
+    SamplerKind, Scene, ShutterCurve, ToneMapper,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Ray Tracing: The Next Week")]
+pub struct Args {
+    /// Built-in scene to render, or the path of a TOML scene file
+    #[arg(default_value = "final_scene")]
+    pub scene: String,
+
+    /// Seed for the scene layout and the sampling; equal seeds render identical images
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Output image; the format is picked from the extension (png, jpg, ppm, hdr, exr,
+    /// pfm)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Writes EXR files with 16-bit half floats instead of 32-bit floats
+    #[arg(long)]
+    pub half: bool,
+
+    /// Image width in pixels
+    #[arg(short = 'w', long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub width: Option<i32>,
+
+    /// Aspect ratio, either as a number or as `W:H`
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Samples per pixel; the maximum per pixel with `--noise-threshold`
+    #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub samples: Option<i32>,
+
+    /// Enables adaptive sampling: pixels stop once the standard error of their
+    /// luminance falls below this fraction of it
+    #[arg(long)]
+    pub noise_threshold: Option<f64>,
+
+    /// Samples every pixel gets before adaptive sampling may stop it
+    #[arg(long)]
+    pub min_samples: Option<i32>,
+
+    /// Renders in passes of this many samples per pixel and rewrites the output
+    /// image after each one
+    #[arg(long)]
+    pub progressive: Option<i32>,
+
+    /// Saves the render state to this file after every pass, and resumes from
+    /// it if it exists, e.g. to add samples to a finished render
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Also writes these output variables of the first hits, each to the
+    /// output path with its name before the extension: `depth`, `normal`,
+    /// `albedo`, `position`, `uv`, `object_id` or `material_id`
+    #[arg(long = "aov", value_delimiter = ',', value_parser = parse_aov)]
+    pub aovs: Vec<Aov>,
+
+    /// Filters the noise out of the finished image, guided by the albedo,
+    /// normals and depth of the first hits
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// Brightens the 8-bit output by this many stops, or darkens it if negative
+    #[arg(long, allow_negative_numbers = true)]
+    pub exposure: Option<f64>,
+
+    /// Tone mapper of the 8-bit output: `clamp` (default), `reinhard`,
+    /// `extended-reinhard`, `aces` or `agx`
+    #[arg(long, value_parser = parse_tone_mapper)]
+    pub tone_map: Option<ToneMapper>,
+
+    /// Luminance that `extended-reinhard` maps to white; by default the
+    /// brightest pixel
+    #[arg(long)]
+    pub white_point: Option<f64>,
+
+    /// Also writes a heat map of the samples taken per pixel to this image
+    #[arg(long)]
+    pub heat_map: Option<PathBuf>,
+
+    /// Maximum number of ray bounces
+    #[arg(short = 'd', long)]
+    pub max_depth: Option<i32>,
+
+    /// Bounces before Russian roulette may end a path
+    #[arg(long)]
+    pub min_bounces: Option<i32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    pub vfov: Option<f64>,
+
+    /// Camera projection: `perspective` (default), `orthographic`, `equirectangular`,
+    /// `angular-fisheye`, `equisolid-fisheye` or `cubemap`
+    #[arg(long, value_parser = parse_projection)]
+    pub projection: Option<Projection>,
+
+    /// Height of the orthographic view in world units; by default what `--vfov`
+    /// covers at the `lookat` point
+    #[arg(long)]
+    pub ortho_height: Option<f64>,
+
+    /// Field of view of the fisheye projections in degrees
+    #[arg(long)]
+    pub fisheye_fov: Option<f64>,
+
+    /// Angle in degrees of the cone of rays through each pixel; 0 keeps everything in focus
+    #[arg(long)]
+    pub defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane in focus
+    #[arg(long)]
+    pub focus_dist: Option<f64>,
+
+    /// Blades of the aperture, at least 3, which give out of focus highlights their shape
+    #[arg(long, value_parser = clap::value_parser!(u32).range(3..))]
+    pub aperture_blades: Option<u32>,
+
+    /// Rotation of the aperture blades in degrees
+    #[arg(long, allow_negative_numbers = true, requires = "aperture_blades")]
+    pub aperture_rotation: Option<f64>,
+
+    /// Grayscale image of the aperture
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = parse_aperture_image,
+        conflicts_with = "aperture_blades"
+    )]
+    pub aperture_image: Option<Arc<ApertureImage>>,
+
+    /// How much the lens barrel cuts off out of focus highlights towards the corners
+    #[arg(long)]
+    pub cat_eye: Option<f64>,
+
+    /// Lens prescription to trace the perspective projection through instead of a thin lens
+    #[arg(long, value_name = "PATH", value_parser = parse_lens)]
+    pub lens: Option<Arc<LensSystem>>,
+
+    /// World units per millimetre of the lens prescription [default: 0.001]
+    #[arg(long)]
+    pub lens_scale: Option<f64>,
+
+    /// Time the shutter opens; objects move between time 0 and 1
+    #[arg(long, allow_negative_numbers = true)]
+    pub shutter_open: Option<f64>,
+
+    /// Time the shutter closes
+    #[arg(long, allow_negative_numbers = true)]
+    pub shutter_close: Option<f64>,
+
+    /// How the shutter opens: `box`, `triangle` or comma-separated weights over the exposure
+    #[arg(long, value_name = "CURVE", value_parser = parse_shutter_curve)]
+    pub shutter_curve: Option<ShutterCurve>,
+
+    /// Time the shutter takes to roll from the top of the image to the bottom
+    #[arg(long, value_name = "TIME", allow_negative_numbers = true)]
+    pub rolling_shutter: Option<f64>,
+
+    /// Background color as `R,G,B`
+    #[arg(short, long, value_parser = parse_color)]
+    pub background: Option<Color>,
+
+    /// Light transport: `naive` (default) or `nee` for next-event estimation
+    #[arg(long, value_parser = parse_integrator)]
+    pub integrator: Option<Integrator>,
+
+    /// Sample generator: `independent` (default), `stratified`, `halton` or `sobol`
+    #[arg(long, value_parser = parse_sampler)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Width and height of the render tiles in pixels
+    #[arg(long)]
+    pub tile_size: Option<i32>,
+
+    /// Order of the render tiles: `scanline` (default), `spiral` or `hilbert`
+    #[arg(long, value_parser = parse_tile_order)]
+    pub tile_order: Option<TileOrder>,
+
+    /// Only renders the pixels from `X0,Y0` up to but not including `X1,Y1`
+    #[arg(long, value_parser = parse_region)]
+    pub crop: Option<Region>,
+
+    /// BVH construction: `sah` (default) or `median`
+    #[arg(long, value_parser = parse_split_method)]
+    pub bvh: Option<SplitMethod>,
+}
+
+impl Args {
+    pub fn load_scene(&self) -> Result<Scene, String> {
+        let split_method = self.bvh.unwrap_or_default();
+        if let Some(scene) = scene::by_name(&self.scene, self.seed.unwrap_or(0), split_method) {
+            return Ok(scene);
+        }
+
+        let path = Path::new(&self.scene);
+        if !path.is_file() {
+            return Err(format!(
+                "unknown scene \"{}\", expected a scene file or one of: {}",
+                self.scene,
+                scene::names().collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        let mut description =
+            SceneDescription::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(seed) = self.seed {
+            description.seed = seed;
+        }
+        description.split_method = split_method;
+        description
+            .build()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| {
+            let stem = Path::new(&self.scene).file_stem().unwrap_or_default();
+            PathBuf::from(stem).with_extension("png")
+        })
+    }
+
+    pub fn exr_precision(&self) -> ExrPrecision {
+        if self.half {
+            ExrPrecision::Half
+        } else {
+            ExrPrecision::Float
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) -> Result<(), String> {
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(samples) = self.samples {
+            camera.samples_per_pixel = samples;
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            camera.noise_threshold = noise_threshold;
+        }
+        if let Some(min_samples) = self.min_samples {
+            camera.min_samples_per_pixel = min_samples;
+        }
+        if let Some(pass_samples) = self.progressive {
+            camera.pass_samples = pass_samples;
+            camera.snapshot_path = Some(self.output_path());
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            camera.checkpoint_path = Some(checkpoint.clone());
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(min_bounces) = self.min_bounces {
+            camera.min_bounces = min_bounces;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(projection) = self.projection {
+            camera.projection = projection;
+        }
+        if let Some(ortho_height) = self.ortho_height {
+            camera.ortho_height = ortho_height;
+        }
+        if let Some(fisheye_fov) = self.fisheye_fov {
+            camera.fisheye_fov = fisheye_fov;
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(blades) = self.aperture_blades {
+            let rotation = match camera.aperture {
+                Aperture::Polygon { rotation, .. } => rotation,
+                _ => 0.0,
+            };
+            camera.aperture = Aperture::Polygon {
+                blades,
+                rotation: self.aperture_rotation.unwrap_or(rotation),
+            };
+        }
+        if let Some(image) = &self.aperture_image {
+            camera.aperture = Aperture::Image(image.clone());
+        }
+        if let Some(cat_eye) = self.cat_eye {
+            camera.cat_eye = cat_eye;
+        }
+        if let Some(lens) = &self.lens {
+            camera.lens = Some(lens.clone());
+        }
+        if let (Some(scale), Some(lens)) = (self.lens_scale, &mut camera.lens) {
+            Arc::make_mut(lens).scale = scale;
+        }
+        if let Some(open) = self.shutter_open {
+            camera.shutter.open = open;
+        }
+        if let Some(close) = self.shutter_close {
+            camera.shutter.close = close;
+        }
+        if let Some(curve) = &self.shutter_curve {
+            camera.shutter.curve = curve.clone();
+        }
+        if let Some(rolling) = self.rolling_shutter {
+            camera.shutter.rolling = rolling;
+        }
+        if let Some(background) = self.background {
+            camera.background = background;
+        }
+        if let Some(integrator) = self.integrator {
+            camera.integrator = integrator;
+        }
+        if let Some(sampler) = self.sampler {
+            camera.sampler = sampler;
+        }
+        if let Some(tile_size) = self.tile_size {
+            camera.tile_size = tile_size;
+        }
+        if let Some(tile_order) = self.tile_order {
+            camera.tile_order = tile_order;
+        }
+        if let Some(crop) = self.crop {
+            camera.crop = Some(crop);
+        }
+        if self.denoise {
+            camera.denoise = true;
+        }
+        if let Some(exposure) = self.exposure {
+            camera.tone_mapping.exposure = exposure;
+        }
+        if let Some(tone_mapper) = self.tone_map {
+            camera.tone_mapping.tone_mapper = tone_mapper;
+        }
+        if let Some(white_point) = self.white_point {
+            camera.tone_mapping.white_point = white_point;
+        }
+        if !self.aovs.is_empty() {
+            camera.aovs = self.aovs.clone();
+        }
+        if camera.projection == Projection::Cubemap && camera.image_width % 6 != 0 {
+            return Err(format!(
+                "a cubemap needs a width that is a multiple of 6, got {}",
+                camera.image_width
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f64, String> {
+    let ratio = match s.split_once(':') {
+        Some((w, h)) => {
+            let w: f64 = w.trim().parse().map_err(|e| format!("{}", e))?;
+            let h: f64 = h.trim().parse().map_err(|e| format!("{}", e))?;
+            w / h
+        }
+        None => s.trim().parse().map_err(|e| format!("{}", e))?,
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(format!("invalid aspect ratio \"{}\"", s))
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let c = s
+        .split(',')
+        .map(|c| c.trim().parse::<f64>().map_err(|e| format!("{}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match c[..] {
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err(format!("expected `R,G,B`, got \"{}\"", s)),
+    }
+}
+
+fn parse_split_method(s: &str) -> Result<SplitMethod, String> {
+    match s {
+        "sah" => Ok(SplitMethod::Sah),
+        "median" => Ok(SplitMethod::Median),
+        _ => Err(format!("expected `sah` or `median`, got \"{}\"", s)),
+    }
+}
+
+fn parse_integrator(s: &str) -> Result<Integrator, String> {
+    match s {
+        "naive" => Ok(Integrator::Naive),
+        "nee" => Ok(Integrator::NextEvent),
+        _ => Err(format!("expected `naive` or `nee`, got \"{}\"", s)),
+    }
+}
+
+fn parse_projection(s: &str) -> Result<Projection, String> {
+    match s {
+        "perspective" => Ok(Projection::Perspective),
+        "orthographic" => Ok(Projection::Orthographic),
+        "equirectangular" => Ok(Projection::Equirectangular),
+        "angular-fisheye" => Ok(Projection::AngularFisheye),
+        "equisolid-fisheye" => Ok(Projection::EquisolidFisheye),
+        "cubemap" => Ok(Projection::Cubemap),
+        _ => Err(format!(
+            "expected `perspective`, `orthographic`, `equirectangular`, `angular-fisheye`, \
+             `equisolid-fisheye` or `cubemap`, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_aperture_image(s: &str) -> Result<Arc<ApertureImage>, String> {
+    ApertureImage::open(s)
+        .map(Arc::new)
+        .map_err(|e| format!("could not load \"{}\": {}", s, e))
+}
+
+fn parse_lens(s: &str) -> Result<Arc<LensSystem>, String> {
+    LensSystem::open(s)
+        .map(Arc::new)
+        .map_err(|e| format!("could not load \"{}\": {}", s, e))
+}
+
+fn parse_shutter_curve(s: &str) -> Result<ShutterCurve, String> {
+    match s {
+        "box" => Ok(ShutterCurve::Box),
+        "triangle" => Ok(ShutterCurve::Triangle),
+        _ => {
+            let weights = s
+                .split(',')
+                .map(|w| w.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| {
+                    format!(
+                        "expected `box`, `triangle` or weights such as `0,1,1,0`, got \"{}\"",
+                        s
+                    )
+                })?;
+            if weights.len() < 2
+                || weights.iter().any(|&w| w < 0.0)
+                || weights.iter().all(|&w| w == 0.0)
+            {
+                return Err(
+                    "expected at least two weights, not negative and not all zero".to_string(),
+                );
+            }
+            Ok(ShutterCurve::Custom(weights))
+        }
+    }
+}
+
+fn parse_sampler(s: &str) -> Result<SamplerKind, String> {
+    match s {
+        "independent" => Ok(SamplerKind::Independent),
+        "stratified" => Ok(SamplerKind::Stratified),
+        "halton" => Ok(SamplerKind::Halton),
+        "sobol" => Ok(SamplerKind::Sobol),
+        _ => Err(format!(
+            "expected `independent`, `stratified`, `halton` or `sobol`, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_tile_order(s: &str) -> Result<TileOrder, String> {
+    match s {
+        "scanline" => Ok(TileOrder::Scanline),
+        "spiral" => Ok(TileOrder::Spiral),
+        "hilbert" => Ok(TileOrder::Hilbert),
+        _ => Err(format!(
+            "expected `scanline`, `spiral` or `hilbert`, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_tone_mapper(s: &str) -> Result<ToneMapper, String> {
+    match s {
+        "clamp" => Ok(ToneMapper::Clamp),
+        "reinhard" => Ok(ToneMapper::Reinhard),
+        "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard),
+        "aces" => Ok(ToneMapper::Aces),
+        "agx" => Ok(ToneMapper::Agx),
+        _ => Err(format!(
+            "expected `clamp`, `reinhard`, `extended-reinhard`, `aces` or `agx`, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_region(s: &str) -> Result<Region, String> {
+    let c = s
+        .split(',')
+        .map(|c| c.trim().parse::<usize>().map_err(|e| format!("{}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match c[..] {
+        [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok(Region::new(x0, y0, x1, y1)),
+        _ => Err(format!(
+            "expected `X0,Y0,X1,Y1` with X0 < X1 and Y0 < Y1, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_aov(s: &str) -> Result<Aov, String> {
+    Aov::from_name(s).ok_or_else(|| {
+        let names = Aov::ALL.map(|aov| format!("`{}`", aov.name()));
+        format!("expected one of {}, got \"{}\"", names.join(", "), s)
+    })
+}
@@ -6,29 +6,39 @@ const INTENSITY: Interval = Interval {
     max: 0.999,
 };
 
-pub fn linear_to_gamma(linear_component: f64) -> f64 {
-    linear_component.sqrt()
+/// The sRGB transfer function, from linear light to encoded values.
+pub fn linear_to_srgb(linear_component: f64) -> f64 {
+    if linear_component <= 0.0031308 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1.0 / 2.4) - 0.055
+    }
 }
 
-impl Color {
-    pub fn color(&self, sample_per_pixel: i32) -> (i32, i32, i32) {
-        let mut r = self.x();
-        let mut g = self.y();
-        let mut b = self.z();
+pub fn srgb_to_linear(encoded_component: f64) -> f64 {
+    if encoded_component <= 0.04045 {
+        encoded_component / 12.92
+    } else {
+        ((encoded_component + 0.055) / 1.055).powf(2.4)
+    }
+}
 
-        let scale = 1.0 / sample_per_pixel as f64;
-        r *= scale;
-        g *= scale;
-        b *= scale;
+impl Color {
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
 
-        r = linear_to_gamma(r);
-        g = linear_to_gamma(g);
-        b = linear_to_gamma(b);
+    /// sRGB encoded 8-bit channels, clamped to the displayable range.
+    pub fn to_rgb8(self) -> [u8; 3] {
+        let r = linear_to_srgb(self.x());
+        let g = linear_to_srgb(self.y());
+        let b = linear_to_srgb(self.z());
 
-        (
-            (256.0 * INTENSITY.clamp(r)) as i32,
-            (256.0 * INTENSITY.clamp(g)) as i32,
-            (256.0 * INTENSITY.clamp(b)) as i32,
-        )
+        [
+            (256.0 * INTENSITY.clamp(r)) as u8,
+            (256.0 * INTENSITY.clamp(g)) as u8,
+            (256.0 * INTENSITY.clamp(b)) as u8,
+        ]
     }
 }
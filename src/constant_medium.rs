@@ -2,7 +2,7 @@ use std::f64::consts::E;
 
 use crate::{
     aabb::Aabb,
-    hittable::{HitRecord, Hittable},
+    hittable::{next_object_id, HitRecord, Hittable},
     interval::*,
     material::Material,
     rtweekend::{self, random_double},
@@ -13,6 +13,7 @@ pub struct ConstantMedium<M: Material> {
     boudary: Box<dyn Hittable>,
     neg_inv_density: f64,
     phase_function: M,
+    id: u32,
 }
 
 impl<M: Material> ConstantMedium<M> {
@@ -21,14 +22,14 @@ impl<M: Material> ConstantMedium<M> {
             boudary: b,
             neg_inv_density: -1.0 / d,
             phase_function: mat,
+            id: next_object_id(),
         }
     }
 }
 
 #[allow(const_item_mutation)]
-
 impl<M: Material> Hittable for ConstantMedium<M> {
-    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &crate::ray::Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         const ENABLE_DEBUG: bool = false;
         let debugging: bool = ENABLE_DEBUG && random_double() < 0.00001;
 
@@ -75,6 +76,7 @@ impl<M: Material> Hittable for ConstantMedium<M> {
                     v: 0.0,
                     front_face: true,
                     mat: &self.phase_function,
+                    object_id: self.id,
                 })
             } else {
                 None
@@ -0,0 +1,225 @@
+//! Edge-avoiding à-trous filter for noisy renders, after "Spatiotemporal
+//! Variance-Guided Filtering" (Schied et al. 2017) without the temporal part.
+//!
+//! The radiance is divided by the albedo first, so that textures are not
+//! blurred, then filtered several times with a 5x5 kernel whose taps spread
+//! out twice as far each time. Every tap is weighted by how closely its
+//! normal, depth and albedo match the center pixel's, and by how much its
+//! luminance differs in units of the expected noise.
+
+use rayon::prelude::*;
+
+use crate::{aov::Aov, color::Color, framebuffer::FrameBuffer, vec3::Vec3};
+
+/// Output variables the filter needs along with the image.
+pub const GUIDES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+const ITERATIONS: u32 = 5;
+const KERNEL: [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Luminance differences are measured in standard deviations of the noise.
+const SIGMA_LUMINANCE: f64 = 4.0;
+/// Exponent of the cosine between normals.
+const SIGMA_NORMAL: f64 = 128.0;
+/// Relative depth difference allowed per pixel of distance.
+const SIGMA_DEPTH: f64 = 0.01;
+/// Squared albedo difference.
+const SIGMA_ALBEDO: f64 = 0.01;
+
+/// Albedo below which a channel is filtered as it is.
+const MIN_ALBEDO: f64 = 0.01;
+
+/// Filters `image` guided by its albedo, normal and depth output variables,
+/// or returns `None` if it does not have them. The noise is taken from the
+/// variances the renderer reports, or estimated from the image without them.
+/// Pixels that were not sampled are left alone.
+pub fn denoise(image: &FrameBuffer) -> Option<FrameBuffer> {
+    let albedo = image.aov(Aov::Albedo)?.pixels();
+    let normal = image.aov(Aov::Normal)?.pixels();
+    let depth = image.aov(Aov::Depth)?.pixels();
+    let (width, height) = (image.width(), image.height());
+
+    let sampled = match image.sample_counts() {
+        Some(counts) => counts.iter().map(|&n| n > 0).collect(),
+        None => vec![true; width * height],
+    };
+
+    let mut colors = image
+        .pixels()
+        .iter()
+        .zip(albedo)
+        .map(|(&c, &a)| demodulate(c, a))
+        .collect::<Vec<_>>();
+    let mut variances = match image.variances() {
+        Some(variances) => variances
+            .iter()
+            .zip(albedo)
+            .map(|(&v, &a)| v / demodulation(a).luminance().powi(2))
+            .collect(),
+        None => local_variances(width, height, &colors, &sampled),
+    };
+
+    let guides = Guides {
+        width,
+        height,
+        albedo,
+        normal,
+        depth,
+        sampled: &sampled,
+    };
+    for iteration in 0..ITERATIONS {
+        (colors, variances) = guides.filter(&colors, &variances, 1 << iteration);
+    }
+
+    let mut denoised = image.clone();
+    for (pixel, (&c, &a)) in colors.iter().zip(albedo).enumerate() {
+        if sampled[pixel] {
+            denoised.set_pixel(pixel % width, pixel / width, c * demodulation(a));
+        }
+    }
+    Some(denoised)
+}
+
+struct Guides<'a> {
+    width: usize,
+    height: usize,
+    albedo: &'a [Color],
+    normal: &'a [Color],
+    depth: &'a [Color],
+    sampled: &'a [bool],
+}
+
+impl Guides<'_> {
+    /// One pass of the filter with its taps `step` pixels apart. Returns the
+    /// filtered colors and their variances.
+    fn filter(&self, colors: &[Color], variances: &[f64], step: usize) -> (Vec<Color>, Vec<f64>) {
+        // The noise at the center is smoothed a little, so that a pixel with
+        // an unlucky estimate does not stop the filter.
+        let deviations = (0..self.width * self.height)
+            .map(|pixel| self.blurred_variance(variances, pixel).sqrt())
+            .collect::<Vec<_>>();
+
+        (0..self.width * self.height)
+            .into_par_iter()
+            .map(|p| {
+                if !self.sampled[p] {
+                    return (colors[p], variances[p]);
+                }
+
+                let (x, y) = ((p % self.width) as isize, (p / self.width) as isize);
+                let luminance = colors[p].luminance();
+                let mut color = Color::default();
+                let mut variance = 0.0;
+                let mut total = 0.0;
+
+                for dy in -2..=2_isize {
+                    for dx in -2..=2_isize {
+                        let (qx, qy) = (x + dx * step as isize, y + dy * step as isize);
+                        if qx < 0 || qy < 0 {
+                            continue;
+                        }
+                        let (qx, qy) = (qx as usize, qy as usize);
+                        if qx >= self.width || qy >= self.height {
+                            continue;
+                        }
+                        let q = qy * self.width + qx;
+                        if !self.sampled[q] {
+                            continue;
+                        }
+
+                        let distance = step as f64 * ((dx * dx + dy * dy) as f64).sqrt();
+                        let luminance_weight = (-(luminance - colors[q].luminance()).abs()
+                            / (SIGMA_LUMINANCE * deviations[p] + 1e-10))
+                            .exp();
+                        let w = KERNEL[dx.unsigned_abs()]
+                            * KERNEL[dy.unsigned_abs()]
+                            * self.guide_weight(p, q, distance)
+                            * luminance_weight;
+
+                        color += w * colors[q];
+                        variance += w * w * variances[q];
+                        total += w;
+                    }
+                }
+
+                // The center pixel always has a weight.
+                (color / total, variance / (total * total))
+            })
+            .unzip()
+    }
+
+    /// How much the surfaces seen through pixels `p` and `q`, `distance`
+    /// pixels apart, look alike.
+    fn guide_weight(&self, p: usize, q: usize, distance: f64) -> f64 {
+        let (np, nq) = (self.normal[p], self.normal[q]);
+        let (zp, zq) = (self.depth[p].x(), self.depth[q].x());
+
+        // Misses have no normal and no depth, and only look like each other.
+        if zp <= 0.0 || zq <= 0.0 {
+            return if zp <= 0.0 && zq <= 0.0 { 1.0 } else { 0.0 };
+        }
+
+        // Normals averaged over an edge are shorter.
+        let cosine = Vec3::dot(Vec3::unit_vector(np), Vec3::unit_vector(nq));
+        let normal = cosine.max(0.0).powf(SIGMA_NORMAL);
+        let depth = (-(zp - zq).abs() / (SIGMA_DEPTH * zp * distance.max(1.0))).exp();
+        let albedo = (-(self.albedo[p] - self.albedo[q]).length_squared() / SIGMA_ALBEDO).exp();
+        normal * depth * albedo
+    }
+
+    /// Variance averaged with its neighbours by a 3x3 Gaussian.
+    fn blurred_variance(&self, variances: &[f64], pixel: usize) -> f64 {
+        const GAUSSIAN: [f64; 2] = [1.0 / 2.0, 1.0 / 4.0];
+
+        let (x, y) = (pixel % self.width, pixel / self.width);
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for qy in y.saturating_sub(1)..(y + 2).min(self.height) {
+            for qx in x.saturating_sub(1)..(x + 2).min(self.width) {
+                let q = qy * self.width + qx;
+                if self.sampled[q] {
+                    let w = GAUSSIAN[qx.abs_diff(x)] * GAUSSIAN[qy.abs_diff(y)];
+                    sum += w * variances[q];
+                    total += w;
+                }
+            }
+        }
+        sum / total.max(f64::EPSILON)
+    }
+}
+
+/// Luminance variance of every pixel's 3x3 neighbourhood, for images without
+/// variances from the renderer.
+fn local_variances(width: usize, height: usize, colors: &[Color], sampled: &[bool]) -> Vec<f64> {
+    (0..width * height)
+        .map(|pixel| {
+            let (x, y) = (pixel % width, pixel / width);
+            let luminances = (y.saturating_sub(1)..(y + 2).min(height))
+                .flat_map(|qy| {
+                    (x.saturating_sub(1)..(x + 2).min(width)).map(move |qx| qy * width + qx)
+                })
+                .filter(|&q| sampled[q])
+                .map(|q| colors[q].luminance())
+                .collect::<Vec<_>>();
+            let n = luminances.len().max(1) as f64;
+            let mean = luminances.iter().sum::<f64>() / n;
+            luminances.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / n
+        })
+        .collect()
+}
+
+/// Per channel factor between the irradiance that is filtered and the
+/// radiance, which is the albedo where there is one.
+fn demodulation(albedo: Color) -> Color {
+    let channel = |a: f64| if a > MIN_ALBEDO { a } else { 1.0 };
+    Color::new(
+        channel(albedo.x()),
+        channel(albedo.y()),
+        channel(albedo.z()),
+    )
+}
+
+fn demodulate(c: Color, albedo: Color) -> Color {
+    let d = demodulation(albedo);
+    Color::new(c.x() / d.x(), c.y() / d.y(), c.z() / d.z())
+}
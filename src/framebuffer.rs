@@ -0,0 +1,298 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use image::{
+    codecs::hdr::HdrEncoder,
+    error::{EncodingError, ImageFormatHint},
+    ImageError, ImageFormat, ImageResult, Rgb, RgbImage,
+};
+
+use crate::{aov::Aov, color::Color, tonemap::ToneMapping};
+
+/// Sample type of the channels of OpenEXR files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPrecision {
+    /// 16-bit half floats, which keep about three decimal digits.
+    Half,
+    /// 32-bit floats.
+    #[default]
+    Float,
+}
+
+/// The file formats [`FrameBuffer::save`] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Exr,
+    Pfm,
+    Hdr,
+    Png,
+    Jpeg,
+    Ppm,
+}
+
+/// Linear radiance for every pixel of a rendered image, stored row by row
+/// from the top-left corner.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Samples averaged into each pixel, when the renderer reports them.
+    sample_counts: Option<Vec<u32>>,
+    /// Variance of the mean luminance of each pixel, when the renderer
+    /// reports it.
+    variances: Option<Vec<f64>>,
+    /// Output variables rendered along with the image.
+    aovs: Vec<(Aov, FrameBuffer)>,
+    /// How the radiance is shown in 8-bit images.
+    tone_mapping: ToneMapping,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+            sample_counts: None,
+            variances: None,
+            aovs: Vec::new(),
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Self {
+            width,
+            height,
+            pixels,
+            sample_counts: None,
+            variances: None,
+            aovs: Vec::new(),
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+
+    pub fn with_sample_counts(mut self, sample_counts: Vec<u32>) -> Self {
+        assert_eq!(sample_counts.len(), self.width * self.height);
+        self.sample_counts = Some(sample_counts);
+        self
+    }
+
+    pub fn with_variances(mut self, variances: Vec<f64>) -> Self {
+        assert_eq!(variances.len(), self.width * self.height);
+        self.variances = Some(variances);
+        self
+    }
+
+    pub fn with_tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    pub fn with_aovs(mut self, aovs: Vec<(Aov, FrameBuffer)>) -> Self {
+        self.aovs = aovs;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, c: Color) {
+        self.pixels[y * self.width + x] = c;
+    }
+
+    pub fn sample_counts(&self) -> Option<&[u32]> {
+        self.sample_counts.as_deref()
+    }
+
+    pub fn variances(&self) -> Option<&[f64]> {
+        self.variances.as_deref()
+    }
+
+    pub fn aovs(&self) -> &[(Aov, FrameBuffer)] {
+        &self.aovs
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&FrameBuffer> {
+        self.aovs
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, image)| image)
+    }
+
+    /// False color image of the sample counts, from black for the fewest
+    /// samples through red and yellow to white for the most.
+    pub fn sample_heat_map(&self) -> Option<FrameBuffer> {
+        let counts = self.sample_counts.as_ref()?;
+        let min = counts.iter().copied().min().unwrap_or(0) as f64;
+        let max = counts.iter().copied().max().unwrap_or(0) as f64;
+        let range = (max - min).max(1.0);
+
+        let pixels = counts
+            .iter()
+            .map(|&count| {
+                let t = 3.0 * (count as f64 - min) / range;
+                Color::new(
+                    t.clamp(0.0, 1.0),
+                    (t - 1.0).clamp(0.0, 1.0),
+                    (t - 2.0).clamp(0.0, 1.0),
+                )
+            })
+            .collect();
+
+        Some(FrameBuffer::from_pixels(self.width, self.height, pixels))
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub fn to_rgb8(&self) -> RgbImage {
+        let pixels = self.tone_mapping.apply(&self.pixels);
+        RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            Rgb(pixels[y as usize * self.width + x as usize].to_rgb8())
+        })
+    }
+
+    /// Writes the image to `path`, choosing the format from its extension.
+    /// `.exr`, `.pfm` and `.hdr` keep the unclamped linear values, and `.exr`
+    /// also the output variables; `.png`, `.jpg`/`.jpeg` and `.ppm` are tone
+    /// mapped, sRGB encoded and quantized to 8 bits.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        let format = Self::format_for(path)
+            .map_err(|e| ImageError::IoError(io::Error::new(io::ErrorKind::Unsupported, e)))?;
+        match format {
+            OutputFormat::Exr => self.save_exr(path, ExrPrecision::default()),
+            OutputFormat::Pfm => Ok(self.save_pfm(path)?),
+            OutputFormat::Hdr => self.save_hdr(path),
+            OutputFormat::Png => self.to_rgb8().save_with_format(path, ImageFormat::Png),
+            OutputFormat::Jpeg => self.to_rgb8().save_with_format(path, ImageFormat::Jpeg),
+            OutputFormat::Ppm => self.to_rgb8().save_with_format(path, ImageFormat::Pnm),
+        }
+    }
+
+    /// The format [`FrameBuffer::save`] picks for `path`, so that an output
+    /// path can be checked before rendering.
+    pub fn format_for<P: AsRef<Path>>(path: P) -> Result<OutputFormat, String> {
+        let path = path.as_ref();
+        let Some(extension) = path.extension() else {
+            return Err(
+                "no extension to pick the image format from, expected one of png, \
+                 jpg, ppm, hdr, exr or pfm"
+                    .to_string(),
+            );
+        };
+        match extension.to_string_lossy().to_ascii_lowercase().as_str() {
+            "exr" => Ok(OutputFormat::Exr),
+            "pfm" => Ok(OutputFormat::Pfm),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "png" => Ok(OutputFormat::Png),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "ppm" => Ok(OutputFormat::Ppm),
+            other => Err(format!(
+                "unsupported image format \"{}\", expected one of png, jpg, ppm, hdr, exr or pfm",
+                other
+            )),
+        }
+    }
+
+    fn save_hdr(&self, path: &Path) -> ImageResult<()> {
+        let data = self
+            .pixels
+            .iter()
+            .map(|c| Rgb([c.x() as f32, c.y() as f32, c.z() as f32]))
+            .collect::<Vec<_>>();
+
+        let writer = BufWriter::new(File::create(path)?);
+        HdrEncoder::new(writer).encode(&data, self.width, self.height)
+    }
+
+    /// Writes an OpenEXR file with the image in its `R`, `G` and `B`
+    /// channels and every output variable in channels named after it, such
+    /// as `normal.X` or `depth.Z`. Ids are always written as 32-bit floats,
+    /// which hold them exactly.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P, precision: ExrPrecision) -> ImageResult<()> {
+        use exr::prelude::{
+            f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes,
+            WritableImage,
+        };
+
+        let channel = |name: String, pixels: &[Color], axis: usize, precision| {
+            let values = pixels.iter().map(|c| c[axis] as f32);
+            let samples = match precision {
+                ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+                ExrPrecision::Float => FlatSamples::F32(values.collect()),
+            };
+            AnyChannel::new(name.as_str(), samples)
+        };
+
+        let mut channels = ["R", "G", "B"]
+            .iter()
+            .enumerate()
+            .map(|(axis, name)| channel(name.to_string(), &self.pixels, axis, precision))
+            .collect::<Vec<_>>();
+        for (aov, image) in &self.aovs {
+            let precision = match aov {
+                Aov::ObjectId | Aov::MaterialId => ExrPrecision::Float,
+                _ => precision,
+            };
+            for (axis, name) in aov.channels().iter().enumerate() {
+                let name = format!("{}.{}", aov.name(), name);
+                channels.push(channel(name, &image.pixels, axis, precision));
+            }
+        }
+
+        let layer = Layer::new(
+            (self.width, self.height),
+            LayerAttributes::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels.into()),
+        );
+        Image::from_layer(layer)
+            .write()
+            .to_file(path)
+            .map_err(|e| match e {
+                exr::error::Error::Io(e) => ImageError::IoError(e),
+                e => ImageError::Encoding(EncodingError::new(
+                    ImageFormatHint::Exact(ImageFormat::OpenExr),
+                    e,
+                )),
+            })
+    }
+
+    /// Portable float map: a short text header, then three little-endian
+    /// 32-bit floats per pixel with the rows from the bottom up.
+    fn save_pfm(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        // The negative scale marks the data as little-endian.
+        write!(w, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = self.pixel(x, y);
+                for v in [c.x(), c.y(), c.z()] {
+                    w.write_all(&(v as f32).to_le_bytes())?;
+                }
+            }
+        }
+        w.flush()
+    }
+}
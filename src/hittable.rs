@@ -1,3 +1,5 @@
+use std::cell::Cell;
+
 use crate::{
     aabb::Aabb,
     interval::*,
@@ -15,6 +17,8 @@ pub struct HitRecord<'a> {
     pub mat: &'a dyn Material,
     pub u: f64,
     pub v: f64,
+    /// Identifies the primitive that was hit, see [`next_object_id`].
+    pub object_id: u32,
 }
 
 impl HitRecord<'_> {
@@ -29,9 +33,40 @@ impl HitRecord<'_> {
     }
 }
 
+thread_local! {
+    static NEXT_OBJECT_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+/// A new id for a primitive; zero means no object. Ids count up in
+/// construction order on each thread, and the scene builders start them over
+/// with [`reset_object_ids`], so a scene built the same way always gets the
+/// same ids.
+pub fn next_object_id() -> u32 {
+    NEXT_OBJECT_ID.with(|id| id.replace(id.get() + 1))
+}
+
+/// Makes [`next_object_id`] on this thread count from one again.
+pub fn reset_object_ids() {
+    NEXT_OBJECT_ID.with(|id| id.set(1));
+}
+
 pub trait Hittable: Sync {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Solid angle density of sampling `direction` from `origin` with
+    /// [`Hittable::random`]. Objects that cannot be sampled return zero.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// A direction from `origin` towards a random point of the object, picked
+    /// with the numbers between 0 and 1 that `rng` returns. Objects that
+    /// cannot be sampled give a direction whose `pdf_value` is zero, which
+    /// callers must not use.
+    fn random(&self, _origin: Point3, _rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub struct Translate {
@@ -53,7 +88,7 @@ impl Translate {
 }
 
 impl Hittable for Translate {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let offset_r = Ray::new_with_time(r.origin() - self.offset, r.direction(), r.time());
 
         if let Some(mut rec) = self.object.hit(&offset_r, ray_t) {
@@ -66,6 +101,7 @@ impl Hittable for Translate {
                 v: rec.v,
                 front_face: rec.front_face,
                 mat: rec.mat,
+                object_id: rec.object_id,
             })
         } else {
             None
@@ -75,6 +111,14 @@ impl Hittable for Translate {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        self.object.random(origin - self.offset, rng)
+    }
 }
 
 pub struct RotateY {
@@ -124,7 +168,7 @@ impl RotateY {
 }
 
 impl Hittable for RotateY {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let mut origin = r.origin();
         let mut direction = r.direction();
 
@@ -137,11 +181,8 @@ impl Hittable for RotateY {
         let rotate_r = Ray::new_with_time(origin, direction, r.time());
 
         if let Some(mut rec) = self.object.hit(&rotate_r, ray_t) {
-            rec.p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-            rec.p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-
-            rec.normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-            rec.normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+            rec.p = self.to_world(rec.p);
+            rec.normal = self.to_world(rec.normal);
 
             Some(HitRecord {
                 p: rec.p,
@@ -151,6 +192,7 @@ impl Hittable for RotateY {
                 v: rec.v,
                 front_face: rec.front_face,
                 mat: rec.mat,
+                object_id: rec.object_id,
             })
         } else {
             None
@@ -160,4 +202,31 @@ impl Hittable for RotateY {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        self.to_world(self.object.random(self.to_object(origin), rng))
+    }
+}
+
+impl RotateY {
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
 }
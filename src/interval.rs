@@ -50,13 +50,13 @@ impl Interval {
     }
 
     pub const EMPTY: Self = Self {
-        min: std::f64::INFINITY,
-        max: std::f64::NEG_INFINITY,
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
     };
 
     pub const UNIVERSE: Self = Self {
-        min: std::f64::NEG_INFINITY,
-        max: std::f64::INFINITY,
+        min: f64::NEG_INFINITY,
+        max: f64::INFINITY,
     };
 }
 
@@ -0,0 +1,433 @@
+//! Camera lenses: the shape of the aperture, which gives out of focus
+//! highlights their shape, and lens prescriptions that are traced surface by
+//! surface instead of the ideal thin lens.
+
+use std::{f64::consts::PI, fs, io, path::Path, sync::Arc};
+
+use image::ImageResult;
+
+use crate::{color::srgb_to_linear, vec3::Vec3};
+
+/// Shape of the opening of the lens.
+#[derive(Debug, Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    /// A regular polygon with `blades` corners on the unit circle, turned
+    /// counterclockwise by `rotation` degrees from a corner pointing right.
+    /// Fewer than 3 blades make no polygon and are sampled as a circle.
+    Polygon { blades: u32, rotation: f64 },
+    /// Light passes in proportion to the brightness of the image.
+    Image(Arc<ApertureImage>),
+}
+
+impl Aperture {
+    /// Maps a point of the unit square onto the aperture, which fits into the
+    /// unit disk, or into the square around it for images.
+    pub fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        match self {
+            Aperture::Polygon { blades, rotation } if *blades >= 3 => {
+                polygon(*blades, *rotation, u)
+            }
+            Aperture::Polygon { .. } | Aperture::Circle => concentric_disk(u),
+            Aperture::Image(image) => image.sample(u),
+        }
+    }
+
+    /// Values identifying the shape, for checkpoint fingerprints.
+    pub(crate) fn fingerprint(&self) -> Vec<u64> {
+        match self {
+            Aperture::Circle => Vec::new(),
+            Aperture::Polygon { blades, rotation } => vec![1, *blades as u64, rotation.to_bits()],
+            Aperture::Image(image) => {
+                let mut fields = vec![2, image.width as u64, image.height as u64];
+                fields.extend(image.rows.iter().chain(&image.columns).map(|c| c.to_bits()));
+                fields
+            }
+        }
+    }
+}
+
+/// A grayscale aperture mask, sampled in proportion to its brightness.
+#[derive(Debug)]
+pub struct ApertureImage {
+    width: usize,
+    height: usize,
+    /// Cumulative distribution of the rows, starting at 0 and ending at 1.
+    rows: Vec<f64>,
+    /// Cumulative distribution of the pixels of each row, `width + 1`
+    /// values per row.
+    columns: Vec<f64>,
+}
+
+impl ApertureImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let image = image::open(path)?.to_luma8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let transmission = image
+            .pixels()
+            .map(|p| srgb_to_linear(p.0[0] as f64 / 255.0))
+            .collect::<Vec<_>>();
+
+        Self::new(width, height, &transmission).ok_or_else(|| {
+            image::ImageError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the aperture image is black",
+            ))
+        })
+    }
+
+    /// An aperture from the transmission of `width * height` pixels in rows
+    /// from the top, or `None` if no light passes.
+    pub fn new(width: usize, height: usize, transmission: &[f64]) -> Option<Self> {
+        let mut rows = vec![0.0];
+        let mut columns = Vec::with_capacity(height * (width + 1));
+        for row in transmission.chunks(width).take(height) {
+            let start = columns.len();
+            columns.push(0.0);
+            for &t in row {
+                columns.push(columns.last().unwrap() + t.max(0.0));
+            }
+            let total = columns[start + width];
+            if total > 0.0 {
+                columns[start..].iter_mut().for_each(|c| *c /= total);
+            }
+            rows.push(rows.last().unwrap() + total);
+        }
+
+        let total = rows[height];
+        if height == 0 || total <= 0.0 {
+            return None;
+        }
+        rows.iter_mut().for_each(|c| *c /= total);
+
+        Some(Self {
+            width,
+            height,
+            rows,
+            columns,
+        })
+    }
+
+    fn sample(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        let (row, fy) = sample_cdf(&self.rows, v);
+        let start = row * (self.width + 1);
+        let (column, fx) = sample_cdf(&self.columns[start..start + self.width + 1], u);
+
+        // The longer side of the image spans the aperture.
+        let size = self.width.max(self.height) as f64;
+        let x = (2.0 * (column as f64 + fx) - self.width as f64) / size;
+        let y = (self.height as f64 - 2.0 * (row as f64 + fy)) / size;
+        (x, y)
+    }
+}
+
+/// The bin of the cumulative distribution `cdf` that `u` falls into, and
+/// where in the bin.
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let bins = cdf.len() - 1;
+    let bin = cdf.partition_point(|&c| c <= u).clamp(1, bins) - 1;
+    let width = cdf[bin + 1] - cdf[bin];
+    let offset = if width > 0.0 {
+        ((u - cdf[bin]) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (bin, offset)
+}
+
+/// Maps the unit square onto a regular polygon: the first coordinate picks
+/// the triangle between the center and one of the edges and the position
+/// along the edge, the second how far out towards the edge.
+fn polygon(blades: u32, rotation: f64, (u, v): (f64, f64)) -> (f64, f64) {
+    let n = blades as f64;
+    let edge = (u * n).floor().min(n - 1.0);
+    let along = u * n - edge;
+    let corner = |k: f64| {
+        let angle = rotation.to_radians() + 2.0 * PI * k / n;
+        (angle.cos(), angle.sin())
+    };
+    let (a, b) = (corner(edge), corner(edge + 1.0));
+    let r = v.sqrt();
+    (
+        r * ((1.0 - along) * a.0 + along * b.0),
+        r * ((1.0 - along) * a.1 + along * b.1),
+    )
+}
+
+/// Maps the unit square onto the unit disk, keeping strata together so that
+/// well spread samples stay well spread on the lens.
+pub(crate) fn concentric_disk((u, v): (f64, f64)) -> (f64, f64) {
+    let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// One surface of a lens prescription, in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    /// Radius of curvature, positive if the center of the sphere lies
+    /// towards the film. Zero for a flat surface such as the aperture stop.
+    pub radius: f64,
+    /// Distance along the axis to the next surface.
+    pub thickness: f64,
+    /// Refractive index of what lies between this surface and the next; zero
+    /// or one for air.
+    pub ior: f64,
+    /// Diameter of the surface.
+    pub aperture: f64,
+}
+
+/// A lens as a list of spherical surfaces, from the front of the lens to the
+/// back. The film is placed behind the last surface so that objects at the
+/// focus distance are sharp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    /// World units per millimetre of the prescription. The default of 0.001
+    /// takes the scene to be in metres.
+    pub scale: f64,
+}
+
+impl LensSystem {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        Self::parse(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a prescription with one surface per line: radius, thickness,
+    /// index of refraction and diameter, separated by whitespace. Text after
+    /// `#` is ignored. The thickness of the last surface is not used.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut elements = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|s| s.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(format!(
+                    "line {}: expected radius, thickness, index of refraction and diameter",
+                    number + 1
+                ));
+            };
+            if aperture <= 0.0 {
+                return Err(format!(
+                    "line {}: the diameter must be positive",
+                    number + 1
+                ));
+            }
+            elements.push(LensElement {
+                radius,
+                thickness,
+                ior,
+                aperture,
+            });
+        }
+        Self::new(elements)
+    }
+
+    /// Checks that `elements` has at least one surface and focuses light.
+    pub fn new(elements: Vec<LensElement>) -> Result<Self, String> {
+        if elements.is_empty() {
+            return Err("the lens has no surfaces".to_string());
+        }
+        if elements
+            .iter()
+            .any(|e| !e.aperture.is_finite() || e.aperture <= 0.0)
+        {
+            return Err("the diameters must be positive".to_string());
+        }
+        let lens = Self {
+            elements,
+            scale: 0.001,
+        };
+        let focal_length = lens.focal_length();
+        if !focal_length.is_finite() || focal_length <= 0.0 {
+            return Err("the lens does not focus light".to_string());
+        }
+        Ok(lens)
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    /// Effective focal length in millimetres.
+    pub fn focal_length(&self) -> f64 {
+        -1.0 / self.paraxial()[1][0]
+    }
+
+    /// Distance in millimetres from the last surface to the film that brings
+    /// objects `distance` world units in front of the lens into focus.
+    /// Distances the lens cannot focus on are treated as infinitely far.
+    pub(crate) fn film_distance(&self, distance: f64) -> f64 {
+        let [[a, b], [c, d]] = self.paraxial();
+        let s = distance / self.scale;
+        let image = -(a * s + b) / (c * s + d);
+        if image.is_finite() && image > 0.0 {
+            image
+        } else {
+            -a / c
+        }
+    }
+
+    /// Radius of the disk behind the last surface through which light from
+    /// the center of the film at `film_distance` reaches the scene.
+    pub(crate) fn exit_pupil(&self, film_distance: f64) -> f64 {
+        const STEPS: usize = 256;
+
+        // `new` leaves no lens without surfaces.
+        let rear = self.elements.last().unwrap().aperture / 2.0;
+        let back = self.length();
+        let film = Vec3::new(0.0, 0.0, back + film_distance);
+        let passes = (1..=STEPS)
+            .map(|k| rear * k as f64 / STEPS as f64)
+            .take_while(|&r| self.trace(film, Vec3::new(r, 0.0, back) - film).is_some())
+            .last();
+        passes.unwrap_or(rear)
+    }
+
+    /// Axial position of the last surface, with the first one at zero.
+    pub(crate) fn length(&self) -> f64 {
+        let n = self.elements.len();
+        self.elements[..n - 1].iter().map(|e| e.thickness).sum()
+    }
+
+    /// Follows a ray from `origin` behind the lens out through its front.
+    /// The axis of the lens is z, with the front surface at zero and the
+    /// film towards positive z. Returns the origin and direction of the ray
+    /// leaving the lens, or `None` if the lens blocks it.
+    pub(crate) fn trace(&self, mut origin: Vec3, mut direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut z = self.length();
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            if i + 1 < self.elements.len() {
+                z -= element.thickness;
+            }
+
+            let center = Vec3::new(0.0, 0.0, z + element.radius);
+            let t = if element.radius == 0.0 {
+                (z - origin.z()) / direction.z()
+            } else {
+                // The near side of the sphere if the surface bulges towards
+                // the ray, the far side otherwise.
+                let nearer = (direction.z() > 0.0) ^ (element.radius < 0.0);
+                intersect_sphere(origin, direction, center, element.radius.abs(), nearer)?
+            };
+            if !t.is_finite() || t <= 0.0 {
+                return None;
+            }
+
+            let p = origin + t * direction;
+            let r = element.aperture / 2.0;
+            if p.x() * p.x() + p.y() * p.y() > r * r {
+                return None;
+            }
+            origin = p;
+
+            if element.radius != 0.0 {
+                let mut normal = Vec3::unit_vector(p - center);
+                if Vec3::dot(normal, direction) > 0.0 {
+                    normal = -normal;
+                }
+                let outside = if i > 0 { self.ior(i - 1) } else { 1.0 };
+                direction = refract(Vec3::unit_vector(direction), normal, self.ior(i) / outside)?;
+            }
+        }
+        Some((origin, direction))
+    }
+
+    fn ior(&self, i: usize) -> f64 {
+        match self.elements[i].ior {
+            ior if ior > 0.0 => ior,
+            _ => 1.0,
+        }
+    }
+
+    /// Ray transfer matrix of paraxial rays, as height and angle times
+    /// refractive index, from the first surface to the last.
+    fn paraxial(&self) -> [[f64; 2]; 2] {
+        let mul = |a: [[f64; 2]; 2], b: [[f64; 2]; 2]| {
+            [
+                [
+                    a[0][0] * b[0][0] + a[0][1] * b[1][0],
+                    a[0][0] * b[0][1] + a[0][1] * b[1][1],
+                ],
+                [
+                    a[1][0] * b[0][0] + a[1][1] * b[1][0],
+                    a[1][0] * b[0][1] + a[1][1] * b[1][1],
+                ],
+            ]
+        };
+
+        let mut m = [[1.0, 0.0], [0.0, 1.0]];
+        let mut before = 1.0;
+        for (i, element) in self.elements.iter().enumerate() {
+            let after = self.ior(i);
+            let power = if element.radius == 0.0 {
+                0.0
+            } else {
+                (after - before) / element.radius
+            };
+            m = mul([[1.0, 0.0], [-power, 1.0]], m);
+            if i + 1 < self.elements.len() {
+                m = mul([[1.0, element.thickness / after], [0.0, 1.0]], m);
+            }
+            before = after;
+        }
+        m
+    }
+}
+
+/// Distance along the ray to the nearer or farther intersection with the
+/// sphere.
+fn intersect_sphere(
+    origin: Vec3,
+    direction: Vec3,
+    center: Vec3,
+    radius: f64,
+    nearer: bool,
+) -> Option<f64> {
+    let oc = origin - center;
+    let a = direction.length_squared();
+    let half_b = Vec3::dot(oc, direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    Some(if nearer {
+        (-half_b - root) / a
+    } else {
+        (-half_b + root) / a
+    })
+}
+
+/// Refracts the unit `direction` at a surface with the unit `normal` facing
+/// against it, or `None` on total internal reflection. `eta` is the index
+/// on the incoming side over the index on the outgoing side.
+fn refract(direction: Vec3, normal: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_in = -Vec3::dot(normal, direction);
+    let sin2_out = eta * eta * (1.0 - cos_in * cos_in).max(0.0);
+    if sin2_out >= 1.0 {
+        return None;
+    }
+    let cos_out = (1.0 - sin2_out).sqrt();
+    Some(eta * direction + (eta * cos_in - cos_out) * normal)
+}
@@ -0,0 +1,64 @@
+//! A parallel ray tracer following "Ray Tracing: The Next Week".
+//!
+//! Worlds are assembled from [`Hittable`] primitives in a [`HittableList`],
+//! optionally wrapped in a [`Bvh`], and rendered by a [`Camera`] into a
+//! [`FrameBuffer`] that can be saved to disk.
+
+pub mod aabb;
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+mod checkpoint;
+pub mod color;
+pub mod constant_medium;
+pub mod denoise;
+pub mod framebuffer;
+pub mod hittable;
+pub mod interval;
+pub mod lens;
+pub mod list;
+pub mod material;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod quads;
+pub mod ray;
+pub mod rtweekend;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod shutter;
+pub mod sphere;
+pub mod texture;
+pub mod tiles;
+pub mod tonemap;
+pub mod triangle;
+pub mod vec3;
+
+pub use crate::{
+    aabb::Aabb,
+    aov::Aov,
+    bvh::Bvh,
+    camera::{Camera, Integrator, Projection},
+    color::Color,
+    constant_medium::ConstantMedium,
+    framebuffer::{ExrPrecision, FrameBuffer, OutputFormat},
+    hittable::{HitRecord, Hittable, RotateY, Translate},
+    interval::Interval,
+    lens::{Aperture, ApertureImage, LensSystem},
+    list::HittableList,
+    material::{
+        Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, Scatter, ScatterRecord,
+    },
+    quads::Quad,
+    ray::{Point3, Ray},
+    sampler::{Sampler, SamplerKind},
+    scene::Scene,
+    shutter::{Shutter, ShutterCurve},
+    sphere::Sphere,
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    tonemap::{ToneMapper, ToneMapping},
+    triangle::{Triangle, TriangleMesh},
+    vec3::Vec3,
+};
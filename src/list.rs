@@ -2,7 +2,8 @@ use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
-    ray::Ray,
+    ray::{Point3, Ray},
+    vec3::Vec3,
 };
 
 #[derive(Default)]
@@ -20,13 +21,17 @@ impl HittableList {
     }
 
     pub fn add(&mut self, object: impl Hittable + 'static) {
+        self.add_boxed(Box::new(object));
+    }
+
+    pub fn add_boxed(&mut self, object: Box<dyn Hittable>) {
         self.bbox = Aabb::new_from_boxes(self.bbox, object.bounding_box().unwrap());
-        self.objects.push(Box::new(object));
+        self.objects.push(object);
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let mut hit_anything = None;
         let mut closest_so_far = ray_t.max;
 
@@ -43,4 +48,28 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        if self.objects.is_empty() {
+            // Like an object that cannot be sampled: `pdf_value` is zero
+            // everywhere, so the direction is never used.
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        let size = self.objects.len();
+        let index = ((rng() * size as f64) as usize).min(size - 1);
+        self.objects[index].random(origin, rng)
+    }
 }
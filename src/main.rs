@@ -1,35 +1,85 @@
-#![allow(dead_code)]
-
-use std::{io::Write, time::Instant};
-
-mod aabb;
-mod bvh;
-mod camera;
-mod color;
-mod constant_medium;
-mod hittable;
-mod interval;
-mod list;
-mod material;
-mod perlin;
-mod quads;
-mod ray;
-mod rtweekend;
-mod scene;
-mod sphere;
-mod texture;
-mod vec3;
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use clap::Parser;
+use image::ImageResult;
+use rtnw::{Aov, FrameBuffer};
+
+mod cli;
 
 fn main() {
     let start_time = Instant::now();
 
-    let image = scene::final_scene();
+    let args = cli::Args::parse();
+
+    let mut scene = match args.load_scene() {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = args.apply(&mut scene.camera) {
+        eprintln!("ERROR: {}", e);
+        std::process::exit(1);
+    }
+    // A path that cannot be written should not cost a whole render.
+    let mut outputs = vec![args.output_path()];
+    outputs.extend(args.heat_map.clone());
+    for path in &outputs {
+        if let Err(e) = FrameBuffer::format_for(path) {
+            eprintln!(
+                "ERROR: Could not write image file \"{}\": {}",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+    match scene.camera.resume() {
+        Ok(Some(samples)) => eprintln!("Resuming: {:.1} samples per pixel", samples),
+        Ok(None) => {}
+        Err(e) => {
+            let path = scene.camera.checkpoint_path.as_ref().unwrap();
+            eprintln!("ERROR: Could not resume from \"{}\": {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    eprintln!("Build: {:?}", start_time.elapsed());
+
+    let render_time = Instant::now();
+    let image = scene.render();
+    eprintln!("Render: {:?}", render_time.elapsed());
 
-    for p in 0..image.len() {
-        let stdout = std::io::stdout();
-        let mut lock = stdout.lock();
+    let path = args.output_path();
+    if has_extension(&path, "exr") {
+        // The output variables go into the same file.
+        check_saved(&path, image.save_exr(&path, args.exr_precision()));
+    } else {
+        check_saved(&path, image.save(&path));
 
-        writeln!(lock, "{} {} {}", image[p].0, image[p].1, image[p].2).unwrap();
+        for (aov, buffer) in image.aovs() {
+            let path = aov_path(&path, *aov);
+            if has_extension(&path, "hdr") || has_extension(&path, "pfm") {
+                check_saved(&path, buffer.save(&path));
+            } else {
+                check_saved(&path, aov.visualize(buffer).save(&path));
+            }
+        }
+    }
+
+    if let Some(path) = &args.heat_map {
+        check_saved(path, image.sample_heat_map().unwrap().save(path));
+    }
+
+    if let Some(counts) = image.sample_counts() {
+        let total = counts.iter().map(|&n| n as u64).sum::<u64>();
+        eprintln!(
+            "Samples: {:.1} per pixel",
+            total as f64 / counts.len().max(1) as f64
+        );
     }
 
     eprintln!("Done!");
@@ -40,3 +90,31 @@ fn main() {
 
     eprintln!("Time: {:?}", elapsed_time);
 }
+
+fn check_saved(path: &Path, result: ImageResult<()>) {
+    if let Err(e) = result {
+        eprintln!(
+            "ERROR: Could not write image file \"{}\": {}",
+            path.display(),
+            e
+        );
+        std::process::exit(1);
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// `image.png` becomes `image.depth.png` and so on.
+fn aov_path(path: &Path, aov: Aov) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(aov.name());
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
@@ -1,38 +1,142 @@
+use std::{cell::Cell, sync::Arc};
+
 use crate::{
-    color::Color, hittable::HitRecord, ray::Ray, rtweekend::random_double, texture::Texture,
+    color::Color,
+    hittable::HitRecord,
+    pdf::{CosinePdf, Pdf, SpherePdf},
+    ray::Ray,
+    rtweekend::PI,
+    texture::Texture,
     vec3::Vec3,
 };
 
-pub trait Material: Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scatter: Scatter,
+}
+
+pub enum Scatter {
+    /// A single outgoing ray, such as a mirror reflection or a refraction,
+    /// that has no density to importance sample against.
+    Specular(Ray),
+    /// Outgoing directions distributed by this density, which
+    /// [`Material::scattering_pdf`] evaluates for any direction.
+    Pdf(Box<dyn Pdf>),
+}
+
+pub trait Material: Send + Sync {
+    /// Random choices take their numbers between 0 and 1 from `rng`.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord>;
+
+    /// Density of the material scattering `r_in` into `scattered`; zero for
+    /// materials that only scatter specularly.
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
 
     fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Color {
         Color::default()
     }
+
+    /// Color of the surface at `rec` for the albedo output, white for
+    /// materials that do not have one.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    /// Identifies the material, see [`next_material_id`].
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+thread_local! {
+    static NEXT_MATERIAL_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+/// A new id for a material. Like object ids they count up per thread and
+/// start over with each scene, see [`reset_material_ids`]; copies of a
+/// material share its id.
+pub fn next_material_id() -> u32 {
+    NEXT_MATERIAL_ID.with(|id| id.replace(id.get() + 1))
+}
+
+/// Makes [`next_material_id`] on this thread count from one again.
+pub fn reset_material_ids() {
+    NEXT_MATERIAL_ID.with(|id| id.set(1));
+}
+
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
+        (**self).scatter(r_in, rec, rng)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        (**self).scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Vec3) -> Color {
+        (**self).emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        (**self).albedo(rec)
+    }
+
+    fn id(&self) -> u32 {
+        (**self).id()
+    }
 }
 
 #[derive(Clone, Copy, Default)]
 pub struct Lambertian<T: Texture> {
     albedo: T,
+    id: u32,
 }
 
 impl<T: Texture> Lambertian<T> {
     pub fn new(a: T) -> Self {
-        Self { albedo: a }
+        Self {
+            albedo: a,
+            id: next_material_id(),
+        }
     }
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            scatter: Scatter::Pdf(Box::new(CosinePdf::new(rec.normal))),
+        })
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = Vec3::dot(rec.normal, Vec3::unit_vector(scattered.direction()));
+        (cos_theta / PI).max(0.0)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.p)
+    }
 
-        let scattered = Ray::new_with_time(rec.p, scatter_direction, r_in.time());
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-        Some((scattered, attenuation))
+    fn id(&self) -> u32 {
+        self.id
     }
 }
 
@@ -40,6 +144,7 @@ impl<T: Texture> Material for Lambertian<T> {
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    id: u32,
 }
 
 impl Metal {
@@ -47,35 +152,53 @@ impl Metal {
         Self {
             albedo: a,
             fuzz: if f < 1.0 { f } else { 1.0 },
+            id: next_material_id(),
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
         let reflected = Vec3::reflect(Vec3::unit_vector(r_in.direction()), rec.normal);
         let scattered = Ray::new_with_time(
             rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+            reflected + self.fuzz * Vec3::random_in_unit_sphere(rng),
             r_in.time(),
         );
-        let attenuation = self.albedo;
         if Vec3::dot(scattered.direction(), rec.normal) > 0.0 {
-            return Some((scattered, attenuation));
+            return Some(ScatterRecord {
+                attenuation: self.albedo,
+                scatter: Scatter::Specular(scattered),
+            });
         }
         None
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct Dielectric {
     ir: f64,
+    id: u32,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             ir: index_of_refraction,
+            id: next_material_id(),
         }
     }
 
@@ -90,7 +213,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
@@ -105,54 +233,96 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > random_double() {
-                Vec3::reflect(unit_direction, rec.normal)
-            } else {
-                Vec3::refract(unit_direction, rec.normal, refraction_ratio)
-            };
+        let reflects = cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > rng();
+        let direction = if reflects {
+            Vec3::reflect(unit_direction, rec.normal)
+        } else {
+            Vec3::refract(unit_direction, rec.normal, refraction_ratio)
+        };
 
         let scattered = Ray::new_with_time(rec.p, direction, r_in.time());
 
-        Some((scattered, attenuation))
+        Some(ScatterRecord {
+            attenuation,
+            scatter: Scatter::Specular(scattered),
+        })
+    }
+
+    fn id(&self) -> u32 {
+        self.id
     }
 }
 
 #[derive(Clone, Copy)]
 pub struct DiffuseLight<T: Texture> {
     emit: T,
+    id: u32,
 }
 
 impl<T: Texture> DiffuseLight<T> {
     pub fn new(a: T) -> Self {
-        Self { emit: a }
+        Self {
+            emit: a,
+            id: next_material_id(),
+        }
     }
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
         None
     }
 
     fn emitted(&self, u: f64, v: f64, p: Vec3) -> Color {
         self.emit.value(u, v, p)
     }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
 }
 
 pub struct Isotropic {
     albedo: Box<dyn Texture>,
+    id: u32,
 }
 
 impl Isotropic {
     pub fn new(a: Box<dyn Texture>) -> Self {
-        Self { albedo: a }
+        Self {
+            albedo: a,
+            id: next_material_id(),
+        }
     }
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let scattered = Ray::new_with_time(rec.p, Vec3::random_unit_vector(), r_in.time());
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-        Some((scattered, attenuation))
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        _rng: &mut dyn FnMut() -> f64,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            scatter: Scatter::Pdf(Box::new(SpherePdf)),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.p)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
     }
 }
@@ -0,0 +1,193 @@
+//! Wavefront OBJ import.
+//!
+//! Polygonal faces are triangulated and every group (`o`/`g`) and `usemtl`
+//! run becomes its own [`TriangleMesh`]. MTL materials are mapped onto the
+//! existing material types:
+//!
+//! - a non-black `Ke` gives a [`DiffuseLight`],
+//! - `d` < 1 or `illum` 4, 6 or 7 gives a [`Dielectric`] with index `Ni`,
+//! - `illum` 3, or a black `Kd` with a non-black `Ks`, gives a [`Metal`] of
+//!   color `Ks` whose fuzz follows the roughness implied by `Ns`,
+//! - anything else is [`Lambertian`] with `map_Kd` or `Kd`.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::ImageError;
+
+use crate::{
+    color::Color,
+    list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    ray::Point3,
+    texture::{ImageTexture, SolidColor, Texture},
+    triangle::TriangleMesh,
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(PathBuf, tobj::LoadError),
+    Texture(PathBuf, ImageError),
+    Mesh(PathBuf, String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Load(path, e) => write!(f, "{}: {}", path.display(), e),
+            ObjError::Texture(path, e) => write!(f, "{}: {}", path.display(), e),
+            ObjError::Mesh(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// One group of an OBJ file with a single material.
+pub struct ObjGroup {
+    pub name: String,
+    pub triangles: HittableList,
+}
+
+/// Loads every triangle of an OBJ file into one list, ready for `Bvh::new`.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<HittableList, ObjError> {
+    let mut list = HittableList::default();
+    for group in load_groups(path, None)? {
+        for object in group.triangles.objects {
+            list.add_boxed(object);
+        }
+    }
+    Ok(list)
+}
+
+/// Loads an OBJ file keeping its groups apart. When `material` is given it is
+/// used for every face instead of the MTL materials.
+pub fn load_groups<P: AsRef<Path>>(
+    path: P,
+    material: Option<Arc<dyn Material>>,
+) -> Result<Vec<ObjGroup>, ObjError> {
+    let path = path.as_ref();
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    let (models, materials) =
+        tobj::load_obj(path, &options).map_err(|e| ObjError::Load(path.to_path_buf(), e))?;
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let materials = match (&material, materials) {
+        (Some(_), _) => Vec::new(),
+        (None, Ok(materials)) => materials
+            .iter()
+            .map(|m| convert_material(m, base_dir))
+            .collect::<Result<Vec<_>, _>>()?,
+        (None, Err(e)) => {
+            eprintln!(
+                "WARNING: Could not load the materials of \"{}\": {}",
+                path.display(),
+                e
+            );
+            Vec::new()
+        }
+    };
+    let default_material: Arc<dyn Material> = material.unwrap_or_else(|| {
+        Arc::new(Lambertian::new(SolidColor::new(Color::new(
+            0.73, 0.73, 0.73,
+        ))))
+    });
+
+    let mut groups = Vec::with_capacity(models.len());
+    for model in models {
+        let mesh = model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let mat = mesh
+            .material_id
+            .and_then(|id| materials.get(id).cloned())
+            .unwrap_or_else(|| default_material.clone());
+
+        let positions = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| Point3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect::<Vec<_>>();
+        // Groups mixing faces with and without `vn`/`vt` only get the
+        // attributes that every vertex has.
+        let mut normals = mesh
+            .normals
+            .chunks_exact(3)
+            .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+            .collect::<Vec<_>>();
+        if normals.len() != positions.len() {
+            normals.clear();
+        }
+        let mut uvs = mesh
+            .texcoords
+            .chunks_exact(2)
+            .map(|uv| (uv[0] as f64, uv[1] as f64))
+            .collect::<Vec<_>>();
+        if uvs.len() != positions.len() {
+            uvs.clear();
+        }
+        let indices = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|i| [i[0] as usize, i[1] as usize, i[2] as usize])
+            .collect();
+
+        let mesh = TriangleMesh::new(positions, normals, uvs, indices, mat)
+            .map_err(|e| ObjError::Mesh(path.to_path_buf(), e))?;
+        groups.push(ObjGroup {
+            name: model.name,
+            triangles: mesh.into_list(),
+        });
+    }
+
+    Ok(groups)
+}
+
+fn convert_material(m: &tobj::Material, base_dir: &Path) -> Result<Arc<dyn Material>, ObjError> {
+    let color = |c: [f32; 3]| Color::new(c[0] as f64, c[1] as f64, c[2] as f64);
+    let is_black = |c: Color| c.length_squared() == 0.0;
+
+    let kd = m.diffuse.map(color).unwrap_or(Color::new(0.73, 0.73, 0.73));
+    let ks = m.specular.map(color).unwrap_or_default();
+    let ke = m.emissive.map(color).unwrap_or_default();
+    let illum = m.illumination_model.unwrap_or(2);
+
+    if !is_black(ke) {
+        return Ok(Arc::new(DiffuseLight::new(SolidColor::new(ke))));
+    }
+
+    if m.dissolve.is_some_and(|d| d < 1.0) || matches!(illum, 4 | 6 | 7) {
+        let ior = m.optical_density.unwrap_or(1.5) as f64;
+        return Ok(Arc::new(Dielectric::new(ior)));
+    }
+
+    if !is_black(ks) && (illum == 3 || is_black(kd)) {
+        // Blinn-Phong exponent to a roughness in [0, 1].
+        let ns = m.shininess.unwrap_or(0.0).max(0.0) as f64;
+        let fuzz = (2.0 / (ns + 2.0)).sqrt();
+        return Ok(Arc::new(Metal::new(ks, fuzz)));
+    }
+
+    let albedo: Arc<dyn Texture> = match &m.diffuse_texture {
+        Some(file) => {
+            let path = base_dir.join(file);
+            let image = ImageTexture::open(&path.to_string_lossy())
+                .map_err(|e| ObjError::Texture(path.clone(), e))?;
+            Arc::new(image)
+        }
+        None => Arc::new(SolidColor::new(kd)),
+    };
+
+    Ok(Arc::new(Lambertian::new(albedo)))
+}
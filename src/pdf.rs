@@ -0,0 +1,96 @@
+//! Probability densities over directions, used to importance sample
+//! scattered rays and light sources.
+
+use crate::{hittable::Hittable, onb::Onb, ray::Point3, rtweekend::PI, vec3::Vec3};
+
+pub trait Pdf {
+    /// Density of `direction` with respect to solid angle.
+    fn value(&self, direction: Vec3) -> f64;
+
+    /// Draws a direction distributed according to this density, taking the
+    /// random numbers from `rng`. It need not be normalized.
+    fn generate(&self, rng: &mut dyn FnMut() -> f64) -> Vec3;
+}
+
+/// Uniform over the whole sphere of directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        Vec3::random_unit_vector(rng)
+    }
+}
+
+/// Proportional to the cosine with the normal, over its hemisphere.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: Vec3) -> Self {
+        Self { uvw: Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine_theta = Vec3::dot(Vec3::unit_vector(direction), self.uvw.w());
+        (cosine_theta / PI).max(0.0)
+    }
+
+    fn generate(&self, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        self.uvw.transform(Vec3::random_cosine_direction(rng))
+    }
+}
+
+/// Directions from `origin` towards the surface of `objects`, as given by
+/// their [`Hittable::pdf_value`] and [`Hittable::random`].
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        self.objects.random(self.origin, rng)
+    }
+}
+
+/// Even mix of two densities.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        Self { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        if rng() < 0.5 {
+            self.p[0].generate(rng)
+        } else {
+            self.p[1].generate(rng)
+        }
+    }
+}
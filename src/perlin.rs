@@ -40,11 +40,10 @@ impl Perlin {
 
         let mut c = [[[Vec3::default(); 2]; 2]; 2];
 
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    c[di][dj][dk] = self.ranvec[self.perm_x[((i + di as i32) & 255) as usize]
-                        as usize
+        for (di, c_di) in c.iter_mut().enumerate() {
+            for (dj, c_dj) in c_di.iter_mut().enumerate() {
+                for (dk, c_dk) in c_dj.iter_mut().enumerate() {
+                    *c_dk = self.ranvec[self.perm_x[((i + di as i32) & 255) as usize] as usize
                         ^ self.perm_y[((j + dj as i32) & 255) as usize] as usize
                         ^ self.perm_z[((k + dk as i32) & 255) as usize] as usize];
                 }
@@ -77,7 +76,7 @@ impl Perlin {
         p
     }
 
-    fn permute(p: &mut Vec<i32>, n: usize) {
+    fn permute(p: &mut [i32], n: usize) {
         for i in (0..n - 1).rev() {
             let target = random_int_range(0, i as i32);
             p.swap(i, target as usize);
@@ -1,11 +1,14 @@
 use crate::{
     aabb::Aabb,
-    hittable::{HitRecord, Hittable},
+    hittable::{next_object_id, HitRecord, Hittable},
+    interval::Interval,
     material::Material,
-    ray::Point3,
+    ray::{Point3, Ray},
+    rtweekend::INFINITY,
     vec3::Vec3, list::HittableList,
 };
 
+#[derive(Clone)]
 pub struct Quad<M: Material> {
     q: Point3,
     u: Vec3,
@@ -15,15 +18,18 @@ pub struct Quad<M: Material> {
     bbox: Aabb,
     normal: Vec3,
     d: f64,
+    area: f64,
+    id: u32,
 }
 
-impl<M: Material + 'static + Copy> Quad<M> {
+impl<M: Material + 'static + Clone> Quad<M> {
     pub fn new(q: Point3, u: Vec3, v: Vec3, mat: M) -> Self {
         let bbox = Aabb::new_from_points(q, q + u + v);
         let n = Vec3::cross(u, v);
         let normal = Vec3::unit_vector(n);
         let d = Vec3::dot(q, normal);
         let w = n / Vec3::dot(n, n);
+        let area = n.length();
         Self {
             q,
             u,
@@ -33,11 +39,20 @@ impl<M: Material + 'static + Copy> Quad<M> {
             normal,
             d,
             w,
+            area,
+            id: next_object_id(),
         }
     }
 
+    fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
     pub fn boxes(a: Point3, b: Point3, mat: M) -> HittableList {
         let mut sides = HittableList::default();
+        // The sides are one object.
+        let id = next_object_id();
 
         let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
         let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
@@ -46,47 +61,27 @@ impl<M: Material + 'static + Copy> Quad<M> {
         let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
         let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
 
-        sides.add(Quad::new(
-            Point3::new(min.x(), min.y(), max.z()),
-            dx,
-            dy,
-            mat,
-        )); // front
+        sides.add(
+            Quad::new(Point3::new(min.x(), min.y(), max.z()), dx, dy, mat.clone()).with_id(id),
+        ); // front
 
-        sides.add(Quad::new(
-            Point3::new(max.x(), min.y(), max.z()),
-            -dz,
-            dy,
-            mat,
-        ));//right
+        sides.add(
+            Quad::new(Point3::new(max.x(), min.y(), max.z()), -dz, dy, mat.clone()).with_id(id),
+        ); //right
 
-        sides.add(Quad::new(
-            Point3::new(max.x(), min.y(), min.z()),
-            -dx,
-            dy,
-            mat,
-        ));//back
+        sides.add(
+            Quad::new(Point3::new(max.x(), min.y(), min.z()), -dx, dy, mat.clone()).with_id(id),
+        ); //back
 
-        sides.add(Quad::new(
-            Point3::new(min.x(), min.y(), min.z()),
-            dz,
-            dy,
-            mat,
-        ));//left
+        sides.add(
+            Quad::new(Point3::new(min.x(), min.y(), min.z()), dz, dy, mat.clone()).with_id(id),
+        ); //left
 
-        sides.add(Quad::new(
-            Point3::new(min.x(), max.y(), max.z()),
-            dx,
-            -dz,
-            mat,
-        ));//top
+        sides.add(
+            Quad::new(Point3::new(min.x(), max.y(), max.z()), dx, -dz, mat.clone()).with_id(id),
+        ); //top
 
-        sides.add(Quad::new(
-            Point3::new(min.x(), min.y(), min.z()),
-            dx,
-            dz,
-            mat,
-        ));//bottom
+        sides.add(Quad::new(Point3::new(min.x(), min.y(), min.z()), dx, dz, mat).with_id(id)); //bottom
 
         sides
     }
@@ -102,7 +97,7 @@ impl<M: Material> Hittable for Quad<M> {
         &self,
         r: &crate::ray::Ray,
         ray_t: &crate::interval::Interval,
-    ) -> Option<crate::hittable::HitRecord> {
+    ) -> Option<crate::hittable::HitRecord<'_>> {
         let denom = Vec3::dot(r.direction(), self.normal);
 
         if denom.abs() < 1e-8 {
@@ -130,11 +125,29 @@ impl<M: Material> Hittable for Quad<M> {
                 mat: &self.mat,
                 u,
                 v,
+                object_id: self.id,
             })
         } else {
             None
         }
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        let Some(rec) = self.hit(&r, &Interval::new(0.001, INFINITY)) else {
+            return 0.0;
+        };
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (Vec3::dot(direction, self.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        let p = self.q + (rng() * self.u) + (rng() * self.v);
+        p - origin
+    }
 }
 
 fn is_interior(a: f64, b: f64) -> Option<(f64, f64)> {
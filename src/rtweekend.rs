@@ -1,14 +1,42 @@
-use rand::Rng;
+use std::cell::RefCell;
 
-pub const INFINITY: f64 = std::f64::INFINITY;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+pub const INFINITY: f64 = f64::INFINITY;
 pub const PI: f64 = std::f64::consts::PI;
 
+thread_local! {
+    static RNG: RefCell<Pcg32> = RefCell::new(Pcg32::seed_from_u64(0));
+}
+
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
 
+/// Restarts the random stream of the current thread. Everything drawn through
+/// `random_*` afterwards on this thread depends only on `seed`.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = Pcg32::seed_from_u64(seed));
+}
+
+/// Derives an independent seed from `seed` and a sequence of stream indices,
+/// e.g. a pixel and a sample number.
+pub fn mix_seed(seed: u64, streams: &[u64]) -> u64 {
+    streams
+        .iter()
+        .fold(splitmix64(seed), |h, &s| splitmix64(h ^ splitmix64(s)))
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub fn random_double() -> f64 {
-    rand::random::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
@@ -16,5 +44,5 @@ pub fn random_double_range(min: f64, max: f64) -> f64 {
 }
 
 pub fn random_int_range(min: i32, max: i32) -> i32 {
-    rand::thread_rng().gen_range(min..max + 1)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max + 1))
 }
@@ -0,0 +1,341 @@
+//! Sample generators for the dimensions of a path: the position in the pixel,
+//! the point on the lens, the time, and a block for every bounce.
+//!
+//! A sampler is restarted for every sample of every pixel and then hands out
+//! dimensions in order, so the same dimension index always feeds the same
+//! decision. Low-discrepancy samplers spread the samples of a pixel evenly in
+//! every dimension, which lowers the noise at a given sample count.
+
+use crate::rtweekend::{mix_seed, random_double};
+
+/// Dimensions drawn for each bounce of a path.
+pub const DIMENSIONS_PER_BOUNCE: usize = 8;
+
+pub trait Sampler {
+    /// Restarts the sampler on sample `index` of `pixel`.
+    fn start_sample(&mut self, pixel: u64, index: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+
+    /// Draws the dimensions of the next bounce, which the materials and
+    /// lights then take in order.
+    fn start_bounce(&mut self) -> BounceSamples {
+        let mut values = [0.0; DIMENSIONS_PER_BOUNCE];
+        for pair in values.chunks_exact_mut(2) {
+            (pair[0], pair[1]) = self.get_2d();
+        }
+        BounceSamples {
+            values,
+            len: DIMENSIONS_PER_BOUNCE,
+            next: 0,
+        }
+    }
+}
+
+/// The dimensions of one bounce. Once they are used up, the random numbers
+/// come from [`random_double`].
+#[derive(Debug, Default)]
+pub struct BounceSamples {
+    values: [f64; DIMENSIONS_PER_BOUNCE],
+    len: usize,
+    next: usize,
+}
+
+impl BounceSamples {
+    pub fn get_1d(&mut self) -> f64 {
+        if self.next < self.len {
+            self.next += 1;
+            self.values[self.next - 1]
+        } else {
+            random_double()
+        }
+    }
+}
+
+/// The samplers [`crate::Camera`] can be configured with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Uniform random numbers.
+    #[default]
+    Independent,
+    /// A jittered grid with a shuffled stratum order in each dimension.
+    Stratified,
+    /// The Halton sequence, with its digits scrambled for every pixel.
+    Halton,
+    /// Pairs of dimensions from the (0, 2) Sobol sequence with Owen scrambling.
+    Sobol,
+}
+
+impl SamplerKind {
+    /// `samples_per_pixel` is the most samples any pixel will take.
+    pub fn build(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, _pixel: u64, _index: u32) {}
+
+    // The bounces draw straight from the generator.
+    fn start_bounce(&mut self) -> BounceSamples {
+        BounceSamples::default()
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        random_double()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_double(), random_double())
+    }
+}
+
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        Self {
+            seed,
+            samples: samples_per_pixel.max(1),
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The stratum of this sample among `count`, in an order shuffled for
+    /// every pixel and dimension.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let scramble = mix_seed(self.seed, &[self.pixel, self.dimension]) as u32;
+        self.dimension += 1;
+        permute(self.index % count, count, scramble)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples);
+        (stratum as f64 + random_double()) / self.samples as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let nx = (self.samples as f64).sqrt().ceil() as u32;
+        let ny = self.samples.div_ceil(nx);
+        let stratum = self.stratum(nx * ny);
+
+        (
+            ((stratum % nx) as f64 + random_double()) / nx as f64,
+            ((stratum / nx) as f64 + random_double()) / ny as f64,
+        )
+    }
+}
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+/// Dimensions past the 64 prime bases of the Halton sequence are uniform
+/// random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let Some(&base) = PRIMES.get(dimension) else {
+            return random_double();
+        };
+
+        let hash = mix_seed(self.seed, &[self.pixel, dimension as u64]);
+        owen_scrambled_radical_inverse(base, self.index as u64, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Every `get_1d` and `get_2d` takes a new pair of Sobol dimensions. The
+/// order of the samples is shuffled independently for every pair, which
+/// decorrelates the pairs while keeping each one a (0, 2) sequence.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.get_2d().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = mix_seed(self.seed, &[self.pixel, self.dimension]);
+        self.dimension += 1;
+
+        let index = nested_uniform_scramble(self.index, hash as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), (hash >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second_dimension(index), (hash >> 16) as u32);
+
+        (bits_to_unit(x), bits_to_unit(y))
+    }
+}
+
+/// The digits of `n` in `base` mirrored about the radix point, each one
+/// permuted depending on the digits before it. Unlike a plain radical
+/// inverse this spreads the first few points of large bases over the whole
+/// interval.
+fn owen_scrambled_radical_inverse(base: u64, mut n: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0;
+    // Also scramble the leading zeros, until the digits fall below the
+    // precision of the result. This also keeps `reversed` within 64 bits.
+    while inv_base_n > f64::EPSILON {
+        let next = n / base;
+        let digit_hash = mix_seed(hash, &[reversed]) as u32;
+        let digit = permute((n - next * base) as u32, base as u32, digit_hash) as u64;
+        reversed = reversed * base + digit;
+        inv_base_n *= inv_base;
+        n = next;
+    }
+    (reversed as f64 * inv_base_n).min(ONE_MINUS_EPSILON)
+}
+
+/// The second dimension of the Sobol sequence as a 32-bit binary fraction.
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut v = 1 << 31;
+    let mut result = 0;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// Owen scrambling of a 32-bit binary fraction, after Burley, "Practical
+/// Hash-based Owen Scrambling" (2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// A pseudo-random permutation of `0..len` selected by `p`, from Kensler,
+/// "Correlated Multi-Jittered Sampling" (2013).
+fn permute(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+
+    (i.wrapping_add(p)) % len
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn bits_to_unit(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
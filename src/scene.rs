@@ -1,22 +1,71 @@
-use image::io::Reader;
-
 use crate::{
-    bvh::Bvh,
+    bvh::{Bvh, SplitMethod},
     camera::Camera,
     color::Color,
     constant_medium::ConstantMedium,
-    hittable::{RotateY, Translate},
+    framebuffer::FrameBuffer,
+    hittable::{self, RotateY, Translate},
     list::HittableList,
-    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Metal},
+    material::{self, Dielectric, DiffuseLight, Isotropic, Lambertian, Metal},
     quads::Quad,
     ray::Point3,
-    rtweekend::{random_double, random_double_range},
+    rtweekend::{self, random_double, random_double_range},
     sphere::Sphere,
-    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor},
     vec3::Vec3,
 };
 
-pub fn random_spheres() -> Vec<(i32, i32, i32)> {
+pub struct Scene {
+    pub world: HittableList,
+    /// Emitters to importance sample; empty to rely on the materials alone.
+    pub lights: HittableList,
+    pub camera: Camera,
+}
+
+impl Scene {
+    pub fn render(&mut self) -> FrameBuffer {
+        if self.lights.objects.is_empty() {
+            self.camera.render(&self.world)
+        } else {
+            self.camera.render_with_lights(&self.world, &self.lights)
+        }
+    }
+}
+
+/// Builds a scene, putting its BVHs together with the given split method.
+pub type SceneBuilder = fn(SplitMethod) -> Scene;
+
+pub const SCENES: &[(&str, SceneBuilder)] = &[
+    ("random_spheres", random_spheres),
+    ("two_spheres", |_| two_spheres()),
+    ("earth", |_| earth()),
+    ("two_perlin_spheres", |_| two_perlin_spheres()),
+    ("quads", |_| quads()),
+    ("simple_light", |_| simple_light()),
+    ("cornell_box", |_| cornell_box()),
+    ("final_scene", final_scene),
+];
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    SCENES.iter().map(|(name, _)| *name)
+}
+
+/// Builds the named scene. `seed` drives both the random layout of the scene
+/// and the sampling of the render, so equal seeds give identical images.
+pub fn by_name(name: &str, seed: u64, split_method: SplitMethod) -> Option<Scene> {
+    let (_, build) = SCENES.iter().find(|(n, _)| *n == name)?;
+
+    rtweekend::seed(seed);
+    hittable::reset_object_ids();
+    material::reset_material_ids();
+    let mut scene = build(split_method);
+    scene.camera.seed = seed;
+    scene.camera.scene_id =
+        rtweekend::mix_seed(0, &name.bytes().map(u64::from).collect::<Vec<_>>());
+    Some(scene)
+}
+
+pub fn random_spheres(split_method: SplitMethod) -> Scene {
     let mut world = HittableList::default();
 
     let _ground_material = Lambertian::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)));
@@ -109,7 +158,7 @@ pub fn random_spheres() -> Vec<(i32, i32, i32)> {
         false,
     ));
 
-    world = HittableList::new(Box::new(Bvh::new(world)));
+    world = HittableList::new(Box::new(Bvh::with_split_method(world, split_method)));
 
     let mut camera = Camera::default();
 
@@ -125,10 +174,14 @@ pub fn random_spheres() -> Vec<(i32, i32, i32)> {
     camera.defocus_angle = 0.6;
     camera.focus_dist = 10.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera,
+    }
 }
 
-pub fn two_spheres() -> Vec<(i32, i32, i32)> {
+pub fn two_spheres() -> Scene {
     let mut world = HittableList::default();
 
     world.add(Sphere::new(
@@ -168,10 +221,14 @@ pub fn two_spheres() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera,
+    }
 }
 
-pub fn earth() -> Vec<(i32, i32, i32)> {
+pub fn earth() -> Scene {
     let mut world = HittableList::default();
 
     let earth_texture = ImageTexture::new("world.jpg");
@@ -192,10 +249,14 @@ pub fn earth() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera,
+    }
 }
 
-pub fn two_perlin_spheres() -> Vec<(i32, i32, i32)> {
+pub fn two_perlin_spheres() -> Scene {
     let mut world = HittableList::default();
 
     let pertext1 = NoiseTexture::new(4.0);
@@ -230,10 +291,14 @@ pub fn two_perlin_spheres() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera,
+    }
 }
 
-pub fn quads() -> Vec<(i32, i32, i32)> {
+pub fn quads() -> Scene {
     let mut world = HittableList::default();
 
     let left_red = Lambertian::new(SolidColor::new(Color::new(1.0, 0.2, 0.2)));
@@ -290,10 +355,14 @@ pub fn quads() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights: HittableList::default(),
+        camera,
+    }
 }
 
-pub fn simple_light() -> Vec<(i32, i32, i32)> {
+pub fn simple_light() -> Scene {
     let mut world = HittableList::default();
 
     let pertext1 = NoiseTexture::new(4.0);
@@ -317,21 +386,27 @@ pub fn simple_light() -> Vec<(i32, i32, i32)> {
 
     let difflight1 = DiffuseLight::new(SolidColor::new(Color::new(4.0, 4.0, 4.0)));
 
-    world.add(Quad::new(
+    let light_quad = Quad::new(
         Point3::new(3.0, 1.0, -2.0),
         Vec3::new(2.0, 0.0, 0.0),
         Vec3::new(0.0, 2.0, 0.0),
         difflight1,
-    ));
+    );
+    world.add(light_quad.clone());
 
     let difflight2 = DiffuseLight::new(SolidColor::new(Color::new(4.0, 4.0, 4.0)));
-    world.add(Sphere::new(
+    let light_sphere = Sphere::new(
         Point3::new(0.0, 7.0, 0.0),
         Vec3::default(),
         2.0,
         difflight2,
         false,
-    ));
+    );
+    world.add(light_sphere);
+
+    let mut lights = HittableList::default();
+    lights.add(light_quad);
+    lights.add(light_sphere);
 
     let mut camera = Camera::default();
 
@@ -346,10 +421,14 @@ pub fn simple_light() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights,
+        camera,
+    }
 }
 
-pub fn cornell_box() -> Vec<(i32, i32, i32)> {
+pub fn cornell_box() -> Scene {
     let mut world = HittableList::default();
 
     let red = Lambertian::new(SolidColor::new(Color::new(0.65, 0.05, 0.05)));
@@ -391,12 +470,16 @@ pub fn cornell_box() -> Vec<(i32, i32, i32)> {
         red,
     ));
 
-    world.add(Quad::new(
+    let light_quad = Quad::new(
         Point3::new(113.0, 554.0, 127.0),
         Vec3::new(330.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 305.0),
         light,
-    ));
+    );
+    world.add(light_quad.clone());
+
+    let mut lights = HittableList::default();
+    lights.add(light_quad);
 
     world.add(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
@@ -432,10 +515,14 @@ pub fn cornell_box() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights,
+        camera,
+    }
 }
 
-pub fn final_scene() -> Vec<(i32, i32, i32)> {
+pub fn final_scene(split_method: SplitMethod) -> Scene {
     let mut boxes1 = HittableList::default();
 
     let ground = Lambertian::new(SolidColor::new(Color::new(0.48, 0.83, 0.53)));
@@ -461,16 +548,20 @@ pub fn final_scene() -> Vec<(i32, i32, i32)> {
 
     let mut world = HittableList::default();
 
-    world.add(Bvh::new(boxes1));
+    world.add(Bvh::with_split_method(boxes1, split_method));
 
     let light = DiffuseLight::new(SolidColor::new(Color::new(9.0, 9.0, 9.0)));
 
-    world.add(Quad::new(
+    let light_quad = Quad::new(
         Point3::new(123.0, 554.0, 147.0),
         Vec3::new(300.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 305.0),
         light,
-    ));
+    );
+    world.add(light_quad.clone());
+
+    let mut lights = HittableList::default();
+    lights.add(light_quad);
 
     let center1 = Point3::new(400.0, 400.0, 200.0);
     let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
@@ -560,7 +651,10 @@ pub fn final_scene() -> Vec<(i32, i32, i32)> {
     }
 
     world.add(Translate::new(
-        Box::new(RotateY::new(Box::new(Bvh::new(boxes2)), 15.0)),
+        Box::new(RotateY::new(
+            Box::new(Bvh::with_split_method(boxes2, split_method)),
+            15.0,
+        )),
         Vec3::new(-100.0, 270.0, 395.0),
     ));
 
@@ -577,5 +671,9 @@ pub fn final_scene() -> Vec<(i32, i32, i32)> {
     camera.vup = Vec3::new(0.0, 1.0, 0.0);
     camera.defocus_angle = 0.0;
 
-    camera.render(&world)
+    Scene {
+        world,
+        lights,
+        camera,
+    }
 }
@@ -0,0 +1,967 @@
+//! Text scene descriptions in TOML.
+//!
+//! A scene file has an optional `seed`, a `[camera]` table whose keys match the
+//! public fields of [`Camera`], named `[textures.*]` and `[materials.*]`, and a
+//! list of `[[objects]]`. Textures, materials and objects are tables with a
+//! single key naming their kind. Emitters listed again under `[[lights]]` are
+//! importance sampled when rendering:
+//!
+//! ```toml
+//! [camera]
+//! lookfrom = [278, 278, -800]
+//! lookat = [278, 278, 0]
+//!
+//! [materials.white.lambertian]
+//! albedo = [0.73, 0.73, 0.73]
+//!
+//! [[objects]]
+//! translate = { offset = [265, 0, 295], object = { rotate_y = { angle = 15, object = { box = { a = [0, 0, 0], b = [165, 330, 165], material = "white" } } } } }
+//! ```
+//!
+//! See `scenes/cornell_box.toml` for a complete example.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    aov::Aov,
+    bvh::{Bvh, SplitMethod},
+    camera::{Camera, Integrator, Projection},
+    constant_medium::ConstantMedium,
+    hittable::{self, Hittable, RotateY, Translate},
+    lens::{Aperture, ApertureImage, LensSystem},
+    list::HittableList,
+    material::{self, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal},
+    obj,
+    quads::Quad,
+    rtweekend,
+    sampler::SamplerKind,
+    scene::Scene,
+    shutter::ShutterCurve,
+    sphere::Sphere,
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    tiles::{Region, TileOrder},
+    tonemap::ToneMapper,
+    triangle::{Triangle, TriangleMesh},
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    Invalid {
+        line: usize,
+        column: usize,
+        message: String,
+        /// The offending line of the scene file.
+        source_line: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LoadError::Invalid {
+                line,
+                column,
+                message,
+                source_line,
+            } => write!(
+                f,
+                "line {}, column {}: {}\n    {}",
+                line, column, message, source_line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A parsed scene file. Its contents are only checked for syntax and types;
+/// references between entries are resolved by [`SceneDescription::build`].
+pub struct SceneDescription {
+    pub seed: u64,
+    /// How the BVHs of the scene are built.
+    pub split_method: SplitMethod,
+    source: String,
+    base_dir: PathBuf,
+    file: SceneFile,
+}
+
+impl SceneDescription {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| LoadError::Io(path.to_path_buf(), e))?;
+        let mut description = Self::parse(&source)?;
+        description.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(description)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, LoadError> {
+        let file: SceneFile = toml::from_str(source).map_err(|e| {
+            invalid(
+                source,
+                e.span().map_or(0, |span| span.start),
+                e.message().to_string(),
+            )
+        })?;
+
+        Ok(Self {
+            seed: file.seed.unwrap_or(0),
+            split_method: SplitMethod::default(),
+            source: source.to_string(),
+            base_dir: PathBuf::new(),
+            file,
+        })
+    }
+
+    pub fn build(&self) -> Result<Scene, LoadError> {
+        rtweekend::seed(self.seed);
+        hittable::reset_object_ids();
+        material::reset_material_ids();
+
+        let mut builder = Builder {
+            description: self,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+        };
+
+        let mut world = HittableList::default();
+        for object in &self.file.objects {
+            world.add_boxed(builder.object(object)?);
+        }
+
+        let mut lights = HittableList::default();
+        for object in &self.file.lights {
+            lights.add_boxed(builder.object(object)?);
+        }
+
+        if let Some(curve) = &self.file.camera.shutter_curve {
+            if let ShutterCurveDesc::Weights(weights) = curve.get_ref() {
+                if weights.len() < 2
+                    || weights.iter().any(|&w| w < 0.0)
+                    || weights.iter().all(|&w| w == 0.0)
+                {
+                    return Err(self.error(
+                        curve.span(),
+                        "field `shutter_curve`: expected at least two weights, not negative \
+                         and not all zero"
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        let counts = [
+            ("image_width", &self.file.camera.image_width),
+            ("samples_per_pixel", &self.file.camera.samples_per_pixel),
+            ("max_depth", &self.file.camera.max_depth),
+        ];
+        for (field, count) in counts {
+            if let Some(count) = count.as_ref().filter(|count| *count.get_ref() <= 0) {
+                return Err(self.error(
+                    count.span(),
+                    format!(
+                        "field `{}`: must be positive, got {}",
+                        field,
+                        count.get_ref()
+                    ),
+                ));
+            }
+        }
+
+        let mut camera = self.file.camera.to_camera();
+        camera.seed = self.seed;
+        if let Some(projection) = &self.file.camera.projection {
+            if camera.projection == Projection::Cubemap && camera.image_width % 6 != 0 {
+                return Err(self.error(
+                    projection.span(),
+                    format!(
+                        "field `projection`: a cubemap needs a multiple of 6 as `image_width`, got {}",
+                        camera.image_width
+                    ),
+                ));
+            }
+        }
+        if let Some(blades) = &self.file.camera.aperture_blades {
+            if *blades.get_ref() < 3 {
+                return Err(self.error(
+                    blades.span(),
+                    format!(
+                        "field `aperture_blades`: an aperture needs at least 3 blades, got {}",
+                        blades.get_ref()
+                    ),
+                ));
+            }
+        }
+        if let Some(rotation) = &self.file.camera.aperture_rotation {
+            if self.file.camera.aperture_blades.is_none() {
+                return Err(self.error(
+                    rotation.span(),
+                    "field `aperture_rotation`: only turns an aperture with `aperture_blades`"
+                        .to_string(),
+                ));
+            }
+        }
+        if let Some(file) = &self.file.camera.aperture_image {
+            if self.file.camera.aperture_blades.is_some() {
+                return Err(self.error(
+                    file.span(),
+                    "field `aperture_image`: the aperture already has `aperture_blades`"
+                        .to_string(),
+                ));
+            }
+            let path = self.base_dir.join(file.get_ref());
+            let image = ApertureImage::open(&path).map_err(|e| {
+                self.error(
+                    file.span(),
+                    format!(
+                        "field `aperture_image`: could not load \"{}\": {}",
+                        path.display(),
+                        e
+                    ),
+                )
+            })?;
+            camera.aperture = Aperture::Image(Arc::new(image));
+        }
+        if let Some(file) = &self.file.camera.lens {
+            let path = self.base_dir.join(file.get_ref());
+            let mut lens = LensSystem::open(&path).map_err(|e| {
+                self.error(
+                    file.span(),
+                    format!("field `lens`: could not load \"{}\": {}", path.display(), e),
+                )
+            })?;
+            if let Some(scale) = self.file.camera.lens_scale {
+                lens.scale = scale;
+            }
+            camera.lens = Some(Arc::new(lens));
+        }
+        camera.snapshot_path = camera.snapshot_path.map(|path| self.base_dir.join(path));
+        camera.checkpoint_path = camera.checkpoint_path.map(|path| self.base_dir.join(path));
+        camera.scene_id = self.scene_id();
+
+        Ok(Scene {
+            world,
+            lights,
+            camera,
+        })
+    }
+
+    /// Hash of the seed, textures, materials, objects and lights. They are
+    /// written out again in a canonical form, so that comments, formatting
+    /// and the order of keys do not count. The camera is left out; the
+    /// settings a resumed render has to share are checked by the camera.
+    fn scene_id(&self) -> u64 {
+        let mut table = toml::from_str::<toml::Table>(&self.source).unwrap_or_default();
+        table.remove("camera");
+        let canonical = toml::Value::Table(table).to_string();
+        rtweekend::mix_seed(0, &canonical.bytes().map(u64::from).collect::<Vec<_>>())
+    }
+
+    fn error(&self, span: Range<usize>, message: String) -> LoadError {
+        invalid(&self.source, span.start, message)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, LoadError> {
+    SceneDescription::open(path)?.build()
+}
+
+fn invalid(source: &str, offset: usize, message: String) -> LoadError {
+    let offset = offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+
+    LoadError::Invalid {
+        line: source[..offset].matches('\n').count() + 1,
+        column: offset - line_start + 1,
+        message,
+        source_line: source[line_start..line_end].trim_end().to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    seed: Option<u64>,
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<ObjectDesc>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f64>,
+    image_width: Option<Spanned<i32>>,
+    samples_per_pixel: Option<Spanned<i32>>,
+    min_samples_per_pixel: Option<i32>,
+    noise_threshold: Option<f64>,
+    pass_samples: Option<i32>,
+    snapshot_path: Option<PathBuf>,
+    checkpoint_path: Option<PathBuf>,
+    max_depth: Option<Spanned<i32>>,
+    min_bounces: Option<i32>,
+    vfov: Option<f64>,
+    projection: Option<Spanned<ProjectionDesc>>,
+    ortho_height: Option<f64>,
+    fisheye_fov: Option<f64>,
+    lookfrom: Option<[f64; 3]>,
+    lookat: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    aperture_blades: Option<Spanned<u32>>,
+    aperture_rotation: Option<Spanned<f64>>,
+    aperture_image: Option<Spanned<String>>,
+    cat_eye: Option<f64>,
+    lens: Option<Spanned<String>>,
+    lens_scale: Option<f64>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    shutter_curve: Option<Spanned<ShutterCurveDesc>>,
+    rolling_shutter: Option<f64>,
+    background: Option<[f64; 3]>,
+    integrator: Option<IntegratorDesc>,
+    sampler: Option<SamplerDesc>,
+    tile_size: Option<i32>,
+    tile_order: Option<TileOrderDesc>,
+    crop: Option<[usize; 4]>,
+    aovs: Option<Vec<AovDesc>>,
+    denoise: Option<bool>,
+    exposure: Option<f64>,
+    tone_map: Option<ToneMapperDesc>,
+    white_point: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionDesc {
+    Perspective,
+    Orthographic,
+    Equirectangular,
+    AngularFisheye,
+    EquisolidFisheye,
+    Cubemap,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(
+    untagged,
+    expecting = "expected `box`, `triangle` or a list of weights over the exposure"
+)]
+enum ShutterCurveDesc {
+    Named(ShutterCurveName),
+    Weights(Vec<f64>),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ShutterCurveName {
+    Box,
+    Triangle,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum IntegratorDesc {
+    Naive,
+    Nee,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum SamplerDesc {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum TileOrderDesc {
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ToneMapperDesc {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Agx,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum AovDesc {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    Uv,
+    ObjectId,
+    MaterialId,
+}
+
+impl CameraDesc {
+    fn to_camera(&self) -> Camera {
+        let mut camera = Camera::default();
+
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(image_width) = &self.image_width {
+            camera.image_width = *image_width.get_ref();
+        }
+        if let Some(samples_per_pixel) = &self.samples_per_pixel {
+            camera.samples_per_pixel = *samples_per_pixel.get_ref();
+        }
+        if let Some(min_samples_per_pixel) = self.min_samples_per_pixel {
+            camera.min_samples_per_pixel = min_samples_per_pixel;
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            camera.noise_threshold = noise_threshold;
+        }
+        if let Some(pass_samples) = self.pass_samples {
+            camera.pass_samples = pass_samples;
+        }
+        if let Some(snapshot_path) = &self.snapshot_path {
+            camera.snapshot_path = Some(snapshot_path.clone());
+        }
+        if let Some(checkpoint_path) = &self.checkpoint_path {
+            camera.checkpoint_path = Some(checkpoint_path.clone());
+        }
+        if let Some(max_depth) = &self.max_depth {
+            camera.max_depth = *max_depth.get_ref();
+        }
+        if let Some(min_bounces) = self.min_bounces {
+            camera.min_bounces = min_bounces;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(projection) = &self.projection {
+            camera.projection = match *projection.get_ref() {
+                ProjectionDesc::Perspective => Projection::Perspective,
+                ProjectionDesc::Orthographic => Projection::Orthographic,
+                ProjectionDesc::Equirectangular => Projection::Equirectangular,
+                ProjectionDesc::AngularFisheye => Projection::AngularFisheye,
+                ProjectionDesc::EquisolidFisheye => Projection::EquisolidFisheye,
+                ProjectionDesc::Cubemap => Projection::Cubemap,
+            };
+        }
+        if let Some(ortho_height) = self.ortho_height {
+            camera.ortho_height = ortho_height;
+        }
+        if let Some(fisheye_fov) = self.fisheye_fov {
+            camera.fisheye_fov = fisheye_fov;
+        }
+        if let Some(lookfrom) = self.lookfrom {
+            camera.lookfrom = vec3(lookfrom);
+        }
+        if let Some(lookat) = self.lookat {
+            camera.lookat = vec3(lookat);
+        }
+        if let Some(vup) = self.vup {
+            camera.vup = vec3(vup);
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(blades) = &self.aperture_blades {
+            camera.aperture = Aperture::Polygon {
+                blades: *blades.get_ref(),
+                rotation: self
+                    .aperture_rotation
+                    .as_ref()
+                    .map_or(0.0, |rotation| *rotation.get_ref()),
+            };
+        }
+        if let Some(cat_eye) = self.cat_eye {
+            camera.cat_eye = cat_eye;
+        }
+        if let Some(open) = self.shutter_open {
+            camera.shutter.open = open;
+        }
+        if let Some(close) = self.shutter_close {
+            camera.shutter.close = close;
+        }
+        if let Some(curve) = &self.shutter_curve {
+            camera.shutter.curve = match curve.get_ref() {
+                ShutterCurveDesc::Named(ShutterCurveName::Box) => ShutterCurve::Box,
+                ShutterCurveDesc::Named(ShutterCurveName::Triangle) => ShutterCurve::Triangle,
+                ShutterCurveDesc::Weights(weights) => ShutterCurve::Custom(weights.clone()),
+            };
+        }
+        if let Some(rolling) = self.rolling_shutter {
+            camera.shutter.rolling = rolling;
+        }
+        if let Some(background) = self.background {
+            camera.background = vec3(background);
+        }
+        if let Some(integrator) = self.integrator {
+            camera.integrator = match integrator {
+                IntegratorDesc::Naive => Integrator::Naive,
+                IntegratorDesc::Nee => Integrator::NextEvent,
+            };
+        }
+        if let Some(sampler) = self.sampler {
+            camera.sampler = match sampler {
+                SamplerDesc::Independent => SamplerKind::Independent,
+                SamplerDesc::Stratified => SamplerKind::Stratified,
+                SamplerDesc::Halton => SamplerKind::Halton,
+                SamplerDesc::Sobol => SamplerKind::Sobol,
+            };
+        }
+        if let Some(tile_size) = self.tile_size {
+            camera.tile_size = tile_size;
+        }
+        if let Some(tile_order) = self.tile_order {
+            camera.tile_order = match tile_order {
+                TileOrderDesc::Scanline => TileOrder::Scanline,
+                TileOrderDesc::Spiral => TileOrder::Spiral,
+                TileOrderDesc::Hilbert => TileOrder::Hilbert,
+            };
+        }
+        if let Some([x0, y0, x1, y1]) = self.crop {
+            camera.crop = Some(Region::new(x0, y0, x1, y1));
+        }
+        if let Some(aovs) = &self.aovs {
+            camera.aovs = aovs
+                .iter()
+                .map(|aov| match aov {
+                    AovDesc::Depth => Aov::Depth,
+                    AovDesc::Normal => Aov::Normal,
+                    AovDesc::Albedo => Aov::Albedo,
+                    AovDesc::Position => Aov::Position,
+                    AovDesc::Uv => Aov::Uv,
+                    AovDesc::ObjectId => Aov::ObjectId,
+                    AovDesc::MaterialId => Aov::MaterialId,
+                })
+                .collect();
+        }
+        if let Some(denoise) = self.denoise {
+            camera.denoise = denoise;
+        }
+        if let Some(exposure) = self.exposure {
+            camera.tone_mapping.exposure = exposure;
+        }
+        if let Some(tone_map) = self.tone_map {
+            camera.tone_mapping.tone_mapper = match tone_map {
+                ToneMapperDesc::Clamp => ToneMapper::Clamp,
+                ToneMapperDesc::Reinhard => ToneMapper::Reinhard,
+                ToneMapperDesc::ExtendedReinhard => ToneMapper::ExtendedReinhard,
+                ToneMapperDesc::Aces => ToneMapper::Aces,
+                ToneMapperDesc::Agx => ToneMapper::Agx,
+            };
+        }
+        if let Some(white_point) = self.white_point {
+            camera.tone_mapping.white_point = white_point;
+        }
+
+        camera
+    }
+}
+
+/// Either an inline color or the name of an entry in `[textures]`.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "expected a color `[r, g, b]` or the name of a texture"
+)]
+enum TextureRef {
+    Color([f64; 3]),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: Spanned<TextureRef>,
+        odd: Spanned<TextureRef>,
+    },
+    Image {
+        file: Spanned<String>,
+    },
+    Noise {
+        scale: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: Spanned<TextureRef>,
+    },
+    Metal {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ior: f64,
+    },
+    DiffuseLight {
+        emit: Spanned<TextureRef>,
+    },
+    Isotropic {
+        albedo: Spanned<TextureRef>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        center2: Option<[f64; 3]>,
+        radius: f64,
+        material: Spanned<String>,
+    },
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: Spanned<String>,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: Spanned<String>,
+    },
+    Mesh {
+        positions: Vec<[f64; 3]>,
+        normals: Option<Spanned<Vec<[f64; 3]>>>,
+        uvs: Option<Spanned<Vec<[f64; 2]>>>,
+        indices: Spanned<Vec<[usize; 3]>>,
+        material: Spanned<String>,
+    },
+    Obj {
+        file: Spanned<String>,
+        material: Option<Spanned<String>>,
+    },
+    #[serde(rename = "box")]
+    Cuboid {
+        a: [f64; 3],
+        b: [f64; 3],
+        material: Spanned<String>,
+    },
+    Translate {
+        offset: [f64; 3],
+        object: Box<ObjectDesc>,
+    },
+    RotateY {
+        angle: f64,
+        object: Box<ObjectDesc>,
+    },
+    ConstantMedium {
+        density: f64,
+        material: Spanned<String>,
+        boundary: Box<ObjectDesc>,
+    },
+    Bvh {
+        objects: Spanned<Vec<ObjectDesc>>,
+    },
+}
+
+struct Builder<'a> {
+    description: &'a SceneDescription,
+    /// Resolved textures, or `None` while a texture is being built so that
+    /// reference cycles can be reported.
+    textures: HashMap<&'a str, Option<Arc<dyn Texture>>>,
+    materials: HashMap<&'a str, Arc<dyn Material>>,
+}
+
+impl<'a> Builder<'a> {
+    fn texture_ref(
+        &mut self,
+        field: &str,
+        r: &'a Spanned<TextureRef>,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        match r.get_ref() {
+            TextureRef::Color(c) => Ok(Arc::new(SolidColor::new(vec3(*c)))),
+            TextureRef::Named(name) => self.texture(field, name, r.span()),
+        }
+    }
+
+    fn texture(
+        &mut self,
+        field: &str,
+        name: &'a str,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        match self.textures.get(name) {
+            Some(Some(texture)) => return Ok(texture.clone()),
+            Some(None) => {
+                return Err(self.description.error(
+                    span,
+                    format!("field `{}`: texture `{}` refers to itself", field, name),
+                ))
+            }
+            None => {}
+        }
+
+        let desc = self.description.file.textures.get(name).ok_or_else(|| {
+            self.description.error(
+                span.clone(),
+                format!("field `{}`: unknown texture `{}`", field, name),
+            )
+        })?;
+
+        self.textures.insert(name, None);
+        let texture: Arc<dyn Texture> = match desc {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(vec3(*color))),
+            TextureDesc::Checker { scale, even, odd } => {
+                let even = self.texture_ref("even", even)?;
+                let odd = self.texture_ref("odd", odd)?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDesc::Image { file } => {
+                let path = self.description.base_dir.join(file.get_ref());
+                let image = ImageTexture::open(&path.to_string_lossy()).map_err(|e| {
+                    self.description.error(
+                        file.span(),
+                        format!("field `file`: could not load \"{}\": {}", path.display(), e),
+                    )
+                })?;
+                Arc::new(image)
+            }
+            TextureDesc::Noise { scale } => Arc::new(NoiseTexture::new(*scale)),
+        };
+        self.textures.insert(name, Some(texture.clone()));
+
+        Ok(texture)
+    }
+
+    fn material(&mut self, r: &'a Spanned<String>) -> Result<Arc<dyn Material>, LoadError> {
+        let name = r.get_ref().as_str();
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+
+        let desc = self.description.file.materials.get(name).ok_or_else(|| {
+            self.description.error(
+                r.span(),
+                format!("field `material`: unknown material `{}`", name),
+            )
+        })?;
+
+        let material: Arc<dyn Material> = match desc {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::new(self.texture_ref("albedo", albedo)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(vec3(*albedo), *fuzz)),
+            MaterialDesc::Dielectric { ior } => Arc::new(Dielectric::new(*ior)),
+            MaterialDesc::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::new(self.texture_ref("emit", emit)?))
+            }
+            MaterialDesc::Isotropic { albedo } => Arc::new(Isotropic::new(Box::new(
+                self.texture_ref("albedo", albedo)?,
+            ))),
+        };
+        self.materials.insert(name, material.clone());
+
+        Ok(material)
+    }
+
+    fn object(&mut self, desc: &'a ObjectDesc) -> Result<Box<dyn Hittable>, LoadError> {
+        let object: Box<dyn Hittable> = match desc {
+            ObjectDesc::Sphere {
+                center,
+                center2,
+                radius,
+                material,
+            } => {
+                let mat = self.material(material)?;
+                match center2 {
+                    Some(center2) => Box::new(Sphere::new(
+                        vec3(*center),
+                        vec3(*center2),
+                        *radius,
+                        mat,
+                        true,
+                    )),
+                    None => Box::new(Sphere::new(
+                        vec3(*center),
+                        Vec3::default(),
+                        *radius,
+                        mat,
+                        false,
+                    )),
+                }
+            }
+            ObjectDesc::Quad { q, u, v, material } => Box::new(Quad::new(
+                vec3(*q),
+                vec3(*u),
+                vec3(*v),
+                self.material(material)?,
+            )),
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => Box::new(Triangle::new_with_attributes(
+                vertices.map(vec3),
+                normals.map(|n| n.map(vec3)),
+                uvs.map(|uv| uv.map(|[u, v]| (u, v))),
+                self.material(material)?,
+            )),
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => {
+                let attributes = [
+                    (
+                        "normals",
+                        normals.as_ref().map(|n| (n.span(), n.get_ref().len())),
+                    ),
+                    (
+                        "uvs",
+                        uvs.as_ref().map(|uv| (uv.span(), uv.get_ref().len())),
+                    ),
+                ];
+                for (field, attribute) in attributes {
+                    let Some((span, len)) = attribute else {
+                        continue;
+                    };
+                    if len != positions.len() {
+                        return Err(self.description.error(
+                            span,
+                            format!(
+                                "field `{}`: expected {} entries, one per position, got {}",
+                                field,
+                                positions.len(),
+                                len
+                            ),
+                        ));
+                    }
+                }
+                if indices.get_ref().is_empty() {
+                    return Err(self.description.error(
+                        indices.span(),
+                        "field `indices`: a mesh needs at least one triangle".to_string(),
+                    ));
+                }
+
+                let mesh = TriangleMesh::new(
+                    positions.iter().copied().map(vec3).collect(),
+                    normals
+                        .iter()
+                        .flat_map(|n| n.get_ref().iter().copied().map(vec3))
+                        .collect(),
+                    uvs.iter()
+                        .flat_map(|uv| uv.get_ref().iter().map(|&[u, v]| (u, v)))
+                        .collect(),
+                    indices.get_ref().clone(),
+                    self.material(material)?,
+                )
+                .map_err(|e| {
+                    self.description
+                        .error(indices.span(), format!("field `indices`: {}", e))
+                })?;
+                Box::new(Bvh::with_split_method(
+                    mesh.into_list(),
+                    self.description.split_method,
+                ))
+            }
+            ObjectDesc::Obj { file, material } => {
+                let material = match material {
+                    Some(material) => Some(self.material(material)?),
+                    None => None,
+                };
+                let path = self.description.base_dir.join(file.get_ref());
+                let groups = obj::load_groups(&path, material).map_err(|e| {
+                    self.description
+                        .error(file.span(), format!("field `file`: {}", e))
+                })?;
+
+                let mut list = HittableList::default();
+                for object in groups.into_iter().flat_map(|g| g.triangles.objects) {
+                    list.add_boxed(object);
+                }
+                if list.objects.is_empty() {
+                    return Err(self.description.error(
+                        file.span(),
+                        format!("field `file`: \"{}\" has no faces", path.display()),
+                    ));
+                }
+                Box::new(Bvh::with_split_method(list, self.description.split_method))
+            }
+            ObjectDesc::Cuboid { a, b, material } => {
+                Box::new(Quad::boxes(vec3(*a), vec3(*b), self.material(material)?))
+            }
+            ObjectDesc::Translate { offset, object } => {
+                Box::new(Translate::new(self.object(object)?, vec3(*offset)))
+            }
+            ObjectDesc::RotateY { angle, object } => {
+                Box::new(RotateY::new(self.object(object)?, *angle))
+            }
+            ObjectDesc::ConstantMedium {
+                density,
+                material,
+                boundary,
+            } => Box::new(ConstantMedium::new(
+                self.object(boundary)?,
+                *density,
+                self.material(material)?,
+            )),
+            ObjectDesc::Bvh { objects } => {
+                if objects.get_ref().is_empty() {
+                    return Err(self.description.error(
+                        objects.span(),
+                        "field `objects`: a bvh needs at least one object".to_string(),
+                    ));
+                }
+                let mut list = HittableList::default();
+                for object in objects.get_ref() {
+                    list.add_boxed(self.object(object)?);
+                }
+                Box::new(Bvh::with_split_method(list, self.description.split_method))
+            }
+        };
+
+        Ok(object)
+    }
+}
+
+fn vec3(e: [f64; 3]) -> Vec3 {
+    Vec3 { e }
+}
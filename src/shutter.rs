@@ -0,0 +1,97 @@
+//! When the camera rays are sent, which decides how moving objects blur.
+//! Objects move between time 0 and 1.
+
+/// How the shutter lets light in over the time it is open.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum ShutterCurve {
+    /// Fully open for the whole exposure.
+    #[default]
+    Box,
+    /// Opens up to the middle of the exposure and closes again, which
+    /// softens the ends of motion blur streaks.
+    Triangle,
+    /// Weights at evenly spaced times from opening to closing, linearly
+    /// interpolated in between.
+    Custom(Vec<f64>),
+}
+
+impl ShutterCurve {
+    /// Maps `u` between 0 and 1 to a fraction of the exposure, spread in
+    /// proportion to the curve. Curves without weight act as a box.
+    pub fn sample(&self, u: f64) -> f64 {
+        match self {
+            ShutterCurve::Box => u,
+            ShutterCurve::Triangle => piecewise_linear(&[0.0, 1.0, 0.0], u),
+            ShutterCurve::Custom(weights) => piecewise_linear(weights, u),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+    pub curve: ShutterCurve,
+    /// Time the shutter takes to roll from the top of the image to the
+    /// bottom, each row being exposed that much later than the one above
+    /// it. Zero exposes the whole image at once.
+    pub rolling: f64,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Self {
+            open: 0.0,
+            close: 1.0,
+            curve: ShutterCurve::Box,
+            rolling: 0.0,
+        }
+    }
+}
+
+impl Shutter {
+    /// Time of a ray at `row`, a fraction of the image height from the top,
+    /// for `u` between 0 and 1.
+    pub fn time(&self, row: f64, u: f64) -> f64 {
+        let time = self.open + (self.close - self.open) * self.curve.sample(u);
+        if self.rolling != 0.0 {
+            time + self.rolling * row
+        } else {
+            time
+        }
+    }
+}
+
+/// Inverts the cumulative distribution of the piecewise linear function
+/// through `weights` over 0 to 1.
+fn piecewise_linear(weights: &[f64], u: f64) -> f64 {
+    let segments = weights.len().saturating_sub(1);
+    let area = |a: f64, b: f64| 0.5 * (a.max(0.0) + b.max(0.0));
+    let total = weights.windows(2).map(|w| area(w[0], w[1])).sum::<f64>();
+    if segments == 0 || !total.is_finite() || total <= 0.0 {
+        return u;
+    }
+
+    let mut remaining = u * total;
+    for (i, w) in weights.windows(2).enumerate() {
+        let (a, b) = (w[0].max(0.0), w[1].max(0.0));
+        let segment = area(a, b);
+        if remaining > segment && i + 1 < segments {
+            remaining -= segment;
+            continue;
+        }
+
+        // Solves a * s + (b - a) * s^2 / 2 = remaining for the position s
+        // within the segment.
+        let slope = b - a;
+        let s = if slope.abs() >= 1e-12 {
+            (-a + (a * a + 2.0 * slope * remaining).max(0.0).sqrt()) / slope
+        } else if a > 0.0 {
+            remaining / a
+        } else {
+            0.0
+        };
+        return (i as f64 + s.clamp(0.0, 1.0)) / segments as f64;
+    }
+    u
+}
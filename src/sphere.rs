@@ -1,5 +1,12 @@
 use crate::{
-    aabb::Aabb, hittable::*, interval::Interval, material::Material, ray::*, rtweekend::PI, vec3::*,
+    aabb::Aabb,
+    hittable::*,
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::*,
+    rtweekend::{INFINITY, PI},
+    vec3::*,
 };
 
 #[derive(Clone, Copy)]
@@ -10,6 +17,7 @@ pub struct Sphere<M: Material> {
     is_moving: bool,
     center_vec: Vec3,
     bbox: Aabb,
+    id: u32,
 }
 
 impl<M: Material> Sphere<M> {
@@ -23,6 +31,7 @@ impl<M: Material> Sphere<M> {
                 is_moving,
                 center_vec: Vec3::default(),
                 bbox: Aabb::new_from_points(center1 - rvec, center1 + rvec),
+                id: next_object_id(),
             },
 
             true => Sphere {
@@ -36,12 +45,15 @@ impl<M: Material> Sphere<M> {
                     let box2 = Aabb::new_from_points(center2 - rvec, center2 + rvec);
                     Aabb::new_from_boxes(box1, box2)
                 },
+                id: next_object_id(),
             },
         }
     }
 
+    /// Moving spheres go from `center1` at time 0 to `center2` at time 1 and
+    /// rest there before and after.
     fn sphere_center(&self, time: f64) -> Vec3 {
-        self.center1 + self.center_vec * time
+        self.center1 + self.center_vec * time.clamp(0.0, 1.0)
     }
 
     fn get_sphere_uv(p: Point3) -> (f64, f64) {
@@ -52,7 +64,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let center = if self.is_moving {
             self.sphere_center(r.time())
         } else {
@@ -91,10 +103,53 @@ impl<M: Material> Hittable for Sphere<M> {
             mat: &self.mat,
             u,
             v,
+            object_id: self.id,
         })
     }
 
     fn bounding_box(&self) -> Option<Aabb> {
         Some(self.bbox)
     }
+
+    // Sampling uses the sphere at time zero, and samples the cone of
+    // directions it subtends from `origin`.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction);
+        if self.hit(&r, &Interval::new(0.001, INFINITY)).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center1 - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        let direction = self.center1 - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector(rng);
+        }
+
+        let uvw = Onb::new(direction);
+        uvw.transform(random_to_sphere(self.radius, distance_squared, rng))
+    }
+}
+
+fn random_to_sphere(radius: f64, distance_squared: f64, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+    let r1 = rng();
+    let r2 = rng();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Vec3::new(x, y, z)
 }
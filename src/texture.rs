@@ -1,13 +1,25 @@
-use image::io::Reader;
+use std::sync::Arc;
+
+use image::{io::Reader, ImageResult, RgbImage};
 
 use crate::{
-    color::Color, interval::Interval, perlin::Perlin, ray::Point3, vec3::Vec3,
+    color::{srgb_to_linear, Color},
+    interval::Interval,
+    perlin::Perlin,
+    ray::Point3,
+    vec3::Vec3,
 };
 
-pub trait Texture: Sync {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Color;
 }
 
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Color {
+        (**self).value(u, v, p)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct SolidColor {
     color_value: Color,
@@ -79,14 +91,21 @@ impl ImageTexture {
             Err(_) => panic!("ERROR: Could not load image file \"{}\".", filename),
         };
 
-        let decode = match open.decode() {
-            Ok(image) => image.to_rgb8(),
+        match open.decode() {
+            Ok(image) => Self::from_image(image.to_rgb8()),
             Err(_) => panic!("ERROR: Could not decode image file \"{}\".", filename),
-        };
+        }
+    }
+
+    pub fn open(filename: &str) -> ImageResult<Self> {
+        let image = Reader::open(filename)?.decode()?;
+        Ok(Self::from_image(image.to_rgb8()))
+    }
 
-        let (width, height) = decode.dimensions();
+    fn from_image(image: RgbImage) -> Self {
+        let (width, height) = image.dimensions();
 
-        let data = decode.into_raw();
+        let data = image.into_raw();
 
         Self {
             data,
@@ -122,12 +141,9 @@ impl Texture for ImageTexture {
             &[255, 0, 255]
         };
 
-        let color_scale = 1.0 / 255.0;
-        Color::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+        // 8-bit images are sRGB encoded, while rendering needs linear light.
+        let linear = |c: u8| srgb_to_linear(c as f64 / 255.0);
+        Color::new(linear(pixel[0]), linear(pixel[1]), linear(pixel[2]))
     }
 }
 
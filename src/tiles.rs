@@ -0,0 +1,120 @@
+//! Splitting the image into square tiles, which [`crate::Camera`] renders in
+//! the chosen order.
+
+/// Order in which tiles are handed to the render threads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    #[default]
+    Scanline,
+    /// Outwards from the center of the image, so the middle shows up first.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each
+    /// other.
+    Hilbert,
+}
+
+/// A rectangle of pixels, from `x0, y0` up to but not including `x1, y1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Region {
+    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    pub fn width(&self) -> usize {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    /// The part of this region that also lies in `other`.
+    pub fn intersect(&self, other: &Region) -> Region {
+        Region {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+}
+
+/// Covers `region` with tiles of `size` pixels square, the last row and
+/// column clipped to it, listed in `order`.
+pub fn tiles(region: &Region, size: usize, order: TileOrder) -> Vec<Region> {
+    let size = size.max(1);
+    let columns = region.width().div_ceil(size);
+    let rows = region.height().div_ceil(size);
+
+    let mut grid = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect::<Vec<_>>();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // Rings of tiles around the center, each walked by angle.
+            let center_x = (columns as f64 - 1.0) / 2.0;
+            let center_y = (rows as f64 - 1.0) / 2.0;
+            let key = |&(column, row): &(usize, usize)| {
+                let dx = column as f64 - center_x;
+                let dy = row as f64 - center_y;
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            grid.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let n = columns.max(rows).next_power_of_two();
+            grid.sort_by_key(|&(column, row)| hilbert_index(n, column, row));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(column, row)| {
+            let x0 = region.x0 + column * size;
+            let y0 = region.y0 + row * size;
+            Region::new(
+                x0,
+                y0,
+                (x0 + size).min(region.x1),
+                (y0 + size).min(region.y1),
+            )
+        })
+        .collect()
+}
+
+/// Distance along the Hilbert curve filling an `n` by `n` grid, with `n` a
+/// power of two.
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so the curve inside it starts and ends at the
+        // right corners.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
@@ -0,0 +1,144 @@
+//! Exposure and tone mapping, which turn the linear radiance of a
+//! [`crate::FrameBuffer`] into colors a display can show.
+
+use crate::color::Color;
+
+/// Compresses radiance above 1 into the displayable range.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    /// Cuts every channel off at 1, so bright colors shift towards white or
+    /// towards their strongest channel.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on the luminance, which never quite reaches white.
+    Reinhard,
+    /// Reinhard that maps [`ToneMapping::white_point`] to white.
+    ExtendedReinhard,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output
+    /// transforms: a filmic curve that desaturates highlights.
+    Aces,
+    /// Troy Sobotka's AgX with the default look, which keeps the hue of
+    /// very bright colors while they fade to white.
+    Agx,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    /// Scales the radiance by two to the power of this many stops.
+    pub exposure: f64,
+    pub tone_mapper: ToneMapper,
+    /// Luminance after exposure that [`ToneMapper::ExtendedReinhard`] maps to
+    /// white. Zero takes the brightest pixel of the image.
+    pub white_point: f64,
+}
+
+impl ToneMapping {
+    /// Linear colors between 0 and 1 for the radiance in `pixels`, to be
+    /// encoded with [`Color::to_rgb8`].
+    pub fn apply(&self, pixels: &[Color]) -> Vec<Color> {
+        let scale = self.exposure.exp2();
+        let white = if self.white_point > 0.0 {
+            self.white_point
+        } else {
+            let brightest = pixels.iter().map(|c| c.luminance()).fold(0.0, f64::max);
+            (scale * brightest).max(1.0)
+        };
+
+        pixels
+            .iter()
+            .map(|&c| {
+                let c = scale * c;
+                let mapped = match self.tone_mapper {
+                    ToneMapper::Clamp => c,
+                    ToneMapper::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+                    ToneMapper::ExtendedReinhard => {
+                        scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+                    }
+                    ToneMapper::Aces => aces(c),
+                    ToneMapper::Agx => agx(c),
+                };
+                Color::new(
+                    mapped.x().clamp(0.0, 1.0),
+                    mapped.y().clamp(0.0, 1.0),
+                    mapped.z().clamp(0.0, 1.0),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Maps the luminance of `c` with `f`, keeping its chromaticity.
+fn scale_luminance(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = c.luminance();
+    if l <= 0.0 {
+        return Color::default();
+    }
+    c * (f(l) / l)
+}
+
+fn aces(c: Color) -> Color {
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let rrt_and_odt = |v: f64| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+    let c = mul(&INPUT, c);
+    mul(
+        &OUTPUT,
+        Color::new(rrt_and_odt(c.x()), rrt_and_odt(c.y()), rrt_and_odt(c.z())),
+    )
+}
+
+fn agx(c: Color) -> Color {
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    // Polynomial fit of the sigmoid of the default look, which takes the
+    // log encoding to display values.
+    let contrast = |v: f64| {
+        let x =
+            (v.max(f64::MIN_POSITIVE).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    let c = mul(&INSET, c);
+    let c = mul(
+        &OUTSET,
+        Color::new(contrast(c.x()), contrast(c.y()), contrast(c.z())),
+    );
+
+    // The curve already includes a display gamma of 2.2.
+    Color::new(
+        c.x().max(0.0).powf(2.2),
+        c.y().max(0.0).powf(2.2),
+        c.z().max(0.0).powf(2.2),
+    )
+}
+
+fn mul(m: &[[f64; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
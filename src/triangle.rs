@@ -0,0 +1,324 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{next_object_id, HitRecord, Hittable},
+    interval::Interval,
+    list::HittableList,
+    material::Material,
+    ray::{Point3, Ray},
+    rtweekend::INFINITY,
+    vec3::Vec3,
+};
+
+pub struct Triangle<M: Material> {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    mat: M,
+    bbox: Aabb,
+    id: u32,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: M) -> Self {
+        Self::new_with_attributes([v0, v1, v2], None, None, mat)
+    }
+
+    /// Builds a triangle with optional per-vertex shading normals and texture
+    /// coordinates. Without `uvs` the barycentric coordinates of the hit are
+    /// used as `(u, v)`.
+    pub fn new_with_attributes(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        mat: M,
+    ) -> Self {
+        Self {
+            vertices,
+            normals,
+            uvs,
+            mat,
+            bbox: triangle_bounding_box(&vertices),
+            id: next_object_id(),
+        }
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        let (t, b1, b2) = intersect(r, ray_t, &self.vertices)?;
+
+        let rec = hit_record(
+            r,
+            t,
+            (b1, b2),
+            &self.vertices,
+            self.normals.as_ref(),
+            self.uvs.as_ref(),
+            &self.mat,
+        );
+        Some(HitRecord {
+            object_id: self.id,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        triangle_pdf_value(&self.vertices, origin, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        random_on_triangle(&self.vertices, rng) - origin
+    }
+}
+
+/// Vertex, normal and texture coordinate buffers shared by all the triangles
+/// of a mesh. Each triangle is three indices into these buffers.
+pub struct TriangleMesh<M: Material> {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    mat: M,
+    /// Shared by all the triangles of the mesh.
+    id: u32,
+}
+
+impl<M: Material + 'static> TriangleMesh<M> {
+    /// `normals` and `uvs` are either empty or hold one entry per position,
+    /// and every index must point at a position.
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mat: M,
+    ) -> Result<Self, String> {
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(format!(
+                "expected {} normals, one per position, got {}",
+                positions.len(),
+                normals.len()
+            ));
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(format!(
+                "expected {} uvs, one per position, got {}",
+                positions.len(),
+                uvs.len()
+            ));
+        }
+        if let Some(i) = indices.iter().flatten().find(|&&i| i >= positions.len()) {
+            return Err(format!(
+                "index {} is out of range for {} positions",
+                i,
+                positions.len()
+            ));
+        }
+
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            mat,
+            id: next_object_id(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Splits the mesh into one hittable per triangle, ready for `Bvh::new`.
+    pub fn into_list(self) -> HittableList {
+        let mesh = Arc::new(self);
+        let mut list = HittableList::default();
+
+        for index in 0..mesh.indices.len() {
+            let vertices = mesh.vertices(index);
+            list.add(MeshTriangle {
+                mesh: mesh.clone(),
+                index,
+                bbox: triangle_bounding_box(&vertices),
+            });
+        }
+
+        list
+    }
+
+    fn vertices(&self, index: usize) -> [Point3; 3] {
+        self.indices[index].map(|i| self.positions[i])
+    }
+}
+
+pub struct MeshTriangle<M: Material> {
+    mesh: Arc<TriangleMesh<M>>,
+    index: usize,
+    bbox: Aabb,
+}
+
+impl<M: Material + 'static> Hittable for MeshTriangle<M> {
+    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        let mesh = &*self.mesh;
+        let vertices = mesh.vertices(self.index);
+        let (t, b1, b2) = intersect(r, ray_t, &vertices)?;
+
+        let indices = mesh.indices[self.index];
+        let normals = (!mesh.normals.is_empty()).then(|| indices.map(|i| mesh.normals[i]));
+        let uvs = (!mesh.uvs.is_empty()).then(|| indices.map(|i| mesh.uvs[i]));
+
+        let rec = hit_record(
+            r,
+            t,
+            (b1, b2),
+            &vertices,
+            normals.as_ref(),
+            uvs.as_ref(),
+            &mesh.mat,
+        );
+        Some(HitRecord {
+            object_id: mesh.id,
+            ..rec
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        triangle_pdf_value(&self.mesh.vertices(self.index), origin, direction)
+    }
+
+    fn random(&self, origin: Point3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        random_on_triangle(&self.mesh.vertices(self.index), rng) - origin
+    }
+}
+
+fn triangle_bounding_box(v: &[Point3; 3]) -> Aabb {
+    let min = Point3::new(
+        v[0].x().min(v[1].x()).min(v[2].x()),
+        v[0].y().min(v[1].y()).min(v[2].y()),
+        v[0].z().min(v[1].z()).min(v[2].z()),
+    );
+    let max = Point3::new(
+        v[0].x().max(v[1].x()).max(v[2].x()),
+        v[0].y().max(v[1].y()).max(v[2].y()),
+        v[0].z().max(v[1].z()).max(v[2].z()),
+    );
+    Aabb::new_from_points(min, max)
+}
+
+/// Möller–Trumbore intersection, returning `t` and the barycentric
+/// coordinates of the hit relative to `v[1]` and `v[2]`.
+fn intersect(r: &Ray, ray_t: &Interval, v: &[Point3; 3]) -> Option<(f64, f64, f64)> {
+    let e1 = v[1] - v[0];
+    let e2 = v[2] - v[0];
+
+    let pvec = Vec3::cross(r.direction(), e2);
+    let det = Vec3::dot(e1, pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - v[0];
+    let b1 = Vec3::dot(tvec, pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = Vec3::cross(tvec, e1);
+    let b2 = Vec3::dot(r.direction(), qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(e2, qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Solid angle density of sampling `direction` from `origin` by picking a
+/// uniform point on the triangle.
+fn triangle_pdf_value(v: &[Point3; 3], origin: Point3, direction: Vec3) -> f64 {
+    let r = Ray::new(origin, direction);
+    let Some((t, _, _)) = intersect(&r, &Interval::new(0.001, INFINITY), v) else {
+        return 0.0;
+    };
+
+    let n = Vec3::cross(v[1] - v[0], v[2] - v[0]);
+    let area = 0.5 * n.length();
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (Vec3::dot(direction, n) / (direction.length() * n.length())).abs();
+
+    distance_squared / (cosine * area)
+}
+
+fn random_on_triangle(v: &[Point3; 3], rng: &mut dyn FnMut() -> f64) -> Point3 {
+    let su = rng().sqrt();
+    let b1 = su * (1.0 - rng());
+    let b2 = su - b1;
+    (1.0 - b1 - b2) * v[0] + b1 * v[1] + b2 * v[2]
+}
+
+fn hit_record<'a>(
+    r: &Ray,
+    t: f64,
+    (b1, b2): (f64, f64),
+    v: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: Option<&[(f64, f64); 3]>,
+    mat: &'a dyn Material,
+) -> HitRecord<'a> {
+    let b0 = 1.0 - b1 - b2;
+
+    let outward_normal = Vec3::unit_vector(Vec3::cross(v[1] - v[0], v[2] - v[0]));
+    let (geometric_normal, front_face) = HitRecord::set_face_normal(r, outward_normal);
+
+    // The shading normal is interpolated from the vertices but kept on the same
+    // side of the surface as the geometric normal.
+    let normal = match normals {
+        Some(n) => {
+            let shading = Vec3::unit_vector(b0 * n[0] + b1 * n[1] + b2 * n[2]);
+            if Vec3::dot(shading, geometric_normal) < 0.0 {
+                -shading
+            } else {
+                shading
+            }
+        }
+        None => geometric_normal,
+    };
+
+    let (u, v) = match uvs {
+        Some(uv) => (
+            b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+            b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+        ),
+        None => (b1, b2),
+    };
+
+    HitRecord {
+        p: r.point_at_parameter(t),
+        normal,
+        t,
+        front_face,
+        mat,
+        u,
+        v,
+        object_id: 0,
+    }
+}
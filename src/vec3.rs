@@ -82,21 +82,24 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
+    /// Draws its coordinates from `rng`, which returns numbers between 0
+    /// and 1, like the other direction samplers below.
+    pub fn random_in_unit_sphere(rng: &mut dyn FnMut() -> f64) -> Vec3 {
         loop {
-            let p = Vec3::random_range(-1.0, 1.0);
+            let mut coordinate = || -1.0 + 2.0 * rng();
+            let p = Vec3::new(coordinate(), coordinate(), coordinate());
             if p.length_squared() < 1.0 {
                 return p;
             }
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        Self::unit_vector(Self::random_in_unit_sphere())
+    pub fn random_unit_vector(rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        Self::unit_vector(Self::random_in_unit_sphere(rng))
     }
 
-    pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
-        let on_unit_sphere = Self::random_in_unit_sphere();
+    pub fn random_on_hemisphere(normal: Vec3, rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        let on_unit_sphere = Self::random_in_unit_sphere(rng);
         if Self::dot(on_unit_sphere, normal) > 0.0 {
             // In the same hemisphere as the normal
             on_unit_sphere
@@ -105,6 +108,19 @@ impl Vec3 {
         }
     }
 
+    /// A direction around `+z` distributed by the cosine with that axis.
+    pub fn random_cosine_direction(rng: &mut dyn FnMut() -> f64) -> Vec3 {
+        let r1 = rng();
+        let r2 = rng();
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
         v - 2.0 * Self::dot(v, n) * n
     }